@@ -0,0 +1,464 @@
+//! JSON API.
+//!
+//! All routes require an API token with the appropriate scope to be supplied as a
+//! Bearer token. Errors are returned as a JSON object with an `error` field. Routes
+//! operate on the default list unless another is named with the `list` query
+//! parameter, except for search, which covers every list unless one is named.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use chrono::{Local, NaiveDate};
+use rocket::http::Status;
+use rocket::request::LenientForm;
+use rocket::response::status;
+use rocket::{Route, State};
+use rocket_contrib::json::{Json, JsonError};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use leaf::lists::DEFAULT_LIST;
+use leaf::models::{self, CompletedTask, NewTask, Position, Priority, Store, Task, TaskId};
+use leaf::quick_add;
+use leaf::recurrence::Recurrence;
+use leaf::search::Document;
+use leaf::tokens::Scope;
+use leaf::users::Users;
+
+use crate::auth::Token;
+use crate::form::TaskFilter;
+use crate::tasks::{COMPLETED_PER_PAGE, SEARCH_RESULTS_PER_LIST};
+
+pub const BASE: &str = "/api/v1";
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    error: String,
+}
+
+pub type Error = status::Custom<Json<ErrorBody>>;
+
+/// A task along with the tags from its description.
+#[derive(Serialize)]
+struct TaskBody {
+    #[serde(flatten)]
+    task: Task,
+    tags: BTreeSet<String>,
+}
+
+#[derive(Serialize)]
+struct Created {
+    id: TaskId,
+}
+
+/// A search result along with the list it was found in.
+#[derive(Serialize)]
+struct SearchResult {
+    list: String,
+    #[serde(flatten)]
+    document: Document,
+}
+
+/// A new task. Fields that are present, including a `null` due date or `none`
+/// priority, take precedence over quick add tokens in the description.
+#[derive(Deserialize)]
+struct NewTaskBody {
+    description: String,
+    #[serde(default, deserialize_with = "present")]
+    due: Option<Option<NaiveDate>>,
+    priority: Option<Priority>,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    recurrence: Option<Recurrence>,
+    #[serde(default)]
+    parent: Option<TaskId>,
+}
+
+/// Changes to a task. Fields that are absent are left unchanged, `due`, `notes`,
+/// `recurrence`, `hidden_until`, and `parent` can be cleared with `null`.
+#[derive(Deserialize)]
+struct TaskChanges {
+    description: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    due: Option<Option<NaiveDate>>,
+    priority: Option<Priority>,
+    #[serde(default, deserialize_with = "present")]
+    notes: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    recurrence: Option<Option<Recurrence>>,
+    #[serde(default, deserialize_with = "present")]
+    hidden_until: Option<Option<NaiveDate>>,
+    #[serde(default, deserialize_with = "present")]
+    parent: Option<Option<TaskId>>,
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![list, show, create, update, move_task, discard, complete, completed, search]
+}
+
+#[get("/tasks?<list>&<filter..>")]
+fn list(
+    token: Option<Token>,
+    list: Option<String>,
+    filter: LenientForm<TaskFilter>,
+    state: State<Users>,
+) -> Result<Json<Vec<TaskBody>>, Error> {
+    let token = authorised(token, Scope::TasksRead)?;
+    let store = find_list(&state, &token, list.as_deref())?;
+    let store = store.lock().unwrap();
+    let tasks = if filter.snoozed {
+        store.snoozed()
+    } else {
+        store.list()
+    };
+    let tasks = tasks
+        .into_iter()
+        .filter(|task| filter.matches(task))
+        .map(TaskBody::from)
+        .collect();
+    Ok(Json(tasks))
+}
+
+#[get("/tasks/<id>?<list>")]
+fn show(
+    token: Option<Token>,
+    id: String,
+    list: Option<String>,
+    state: State<Users>,
+) -> Result<Json<TaskBody>, Error> {
+    let token = authorised(token, Scope::TasksRead)?;
+    let id = parse_id(&id)?;
+    let store = find_list(&state, &token, list.as_deref())?;
+    let store = store.lock().unwrap();
+    find_task(store.all(), id).map(|task| Json(TaskBody::from(task)))
+}
+
+#[post("/tasks?<list>", format = "json", data = "<task>")]
+fn create(
+    token: Option<Token>,
+    list: Option<String>,
+    task: Result<Json<NewTaskBody>, JsonError>,
+    state: State<Users>,
+) -> Result<status::Created<Json<Created>>, Error> {
+    let token = authorised(token, Scope::TasksAdd)?;
+    let task = task.map_err(json_error)?.into_inner();
+    let parsed = quick_add::parse(&task.description, Local::today().naive_local());
+    if parsed.description.is_empty() {
+        return Err(error(
+            Status::UnprocessableEntity,
+            "description must not be empty",
+        ));
+    }
+    let list = list.or_else(|| parsed.list.clone());
+    let store = find_list(&state, &token, list.as_deref())?;
+    let task = NewTask {
+        due: task.due.unwrap_or(parsed.due),
+        priority: task.priority.unwrap_or(parsed.priority),
+        notes: task.notes.as_deref().and_then(models::clean_notes),
+        recurrence: task.recurrence,
+        parent: task.parent,
+        ..NewTask::from(parsed)
+    };
+
+    let mut store = store.lock().unwrap();
+    if let Some(parent) = task.parent {
+        if !store.can_nest(None, parent) {
+            return Err(parent_error());
+        }
+    }
+    log::debug!("create_task: {:?}", task);
+    let id = store
+        .add(task)
+        .map_err(|err| internal_error("Failed to add new task", err))?;
+
+    let location = match list {
+        Some(list) => format!("{}/tasks/{}?list={}", BASE, id, list),
+        None => format!("{}/tasks/{}", BASE, id),
+    };
+    Ok(status::Created(location, Some(Json(Created { id }))))
+}
+
+#[patch("/tasks/<id>?<list>", format = "json", data = "<changes>")]
+fn update(
+    token: Option<Token>,
+    id: String,
+    list: Option<String>,
+    changes: Result<Json<TaskChanges>, JsonError>,
+    state: State<Users>,
+) -> Result<Json<TaskBody>, Error> {
+    let token = authorised(token, Scope::TasksEdit)?;
+    let id = parse_id(&id)?;
+    let store = find_list(&state, &token, list.as_deref())?;
+    let changes = changes.map_err(json_error)?.into_inner();
+    if let Some(ref description) = changes.description {
+        if description.trim().is_empty() {
+            return Err(error(
+                Status::UnprocessableEntity,
+                "description must not be empty",
+            ));
+        }
+    }
+
+    let mut store = store.lock().unwrap();
+    find_task(store.all(), id)?;
+    if let Some(Some(parent)) = changes.parent {
+        if !store.can_nest(Some(id), parent) {
+            return Err(parent_error());
+        }
+    }
+    store
+        .edit(id, |task| {
+            if let Some(description) = changes.description {
+                task.description = description;
+            }
+            if let Some(due) = changes.due {
+                task.due = due;
+            }
+            if let Some(priority) = changes.priority {
+                task.priority = priority;
+            }
+            if let Some(notes) = changes.notes {
+                task.notes = notes.as_deref().and_then(models::clean_notes);
+            }
+            if let Some(recurrence) = changes.recurrence {
+                task.recurrence = recurrence;
+            }
+            if let Some(hidden_until) = changes.hidden_until {
+                task.hidden_until = hidden_until;
+            }
+            if let Some(parent) = changes.parent {
+                task.parent = parent;
+            }
+        })
+        .map_err(|err| internal_error("Failed to update task", err))?;
+
+    find_task(store.all(), id).map(|task| Json(TaskBody::from(task)))
+}
+
+#[post("/tasks/<id>/move?<list>", format = "json", data = "<position>")]
+fn move_task(
+    token: Option<Token>,
+    id: String,
+    list: Option<String>,
+    position: Result<Json<Position>, JsonError>,
+    state: State<Users>,
+) -> Result<status::NoContent, Error> {
+    let token = authorised(token, Scope::TasksEdit)?;
+    let id = parse_id(&id)?;
+    let store = find_list(&state, &token, list.as_deref())?;
+    let position = position.map_err(json_error)?.into_inner();
+
+    let mut store = store.lock().unwrap();
+    find_task(store.all(), id)?;
+    match position {
+        Position::Before(other) | Position::After(other) if other == id => {
+            return Err(error(
+                Status::UnprocessableEntity,
+                "a task can't be moved relative to itself",
+            ));
+        }
+        Position::Before(other) | Position::After(other) => {
+            find_task(store.all(), other).map_err(|_| {
+                error(
+                    Status::UnprocessableEntity,
+                    "task to move relative to not found",
+                )
+            })?;
+        }
+        Position::Top | Position::Bottom => {}
+    }
+    store
+        .move_task(id, position)
+        .map_err(|err| internal_error("Failed to move task", err))?;
+
+    Ok(status::NoContent)
+}
+
+#[delete("/tasks/<id>?<reason>&<list>")]
+fn discard(
+    token: Option<Token>,
+    id: String,
+    reason: Option<String>,
+    list: Option<String>,
+    state: State<Users>,
+) -> Result<status::NoContent, Error> {
+    let token = authorised(token, Scope::TasksEdit)?;
+    let id = parse_id(&id)?;
+    let store = find_list(&state, &token, list.as_deref())?;
+    let mut store = store.lock().unwrap();
+    find_task(store.all(), id)?;
+    store
+        .discard(&[id], reason.as_deref())
+        .map_err(|err| internal_error("Failed to discard task", err))?;
+
+    Ok(status::NoContent)
+}
+
+#[post("/tasks/<id>/complete?<list>")]
+fn complete(
+    token: Option<Token>,
+    id: String,
+    list: Option<String>,
+    state: State<Users>,
+) -> Result<status::NoContent, Error> {
+    let token = authorised(token, Scope::TasksComplete)?;
+    let id = parse_id(&id)?;
+    let store = find_list(&state, &token, list.as_deref())?;
+    let mut store = store.lock().unwrap();
+    find_task(store.all(), id)?;
+    store
+        .complete(&[id])
+        .map_err(|err| internal_error("Failed to complete task", err))?;
+
+    Ok(status::NoContent)
+}
+
+#[get("/completed?<page>&<list>")]
+fn completed(
+    token: Option<Token>,
+    page: Option<usize>,
+    list: Option<String>,
+    state: State<Users>,
+) -> Result<Json<Vec<CompletedTask>>, Error> {
+    let token = authorised(token, Scope::TasksRead)?;
+    let page = page.unwrap_or(1).max(1);
+    let store = find_list(&state, &token, list.as_deref())?;
+    let completed = {
+        let store = store.lock().unwrap();
+        store
+            .completed()
+            .map_err(|err| internal_error("Failed to read completed tasks", err))?
+    };
+
+    Ok(Json(
+        completed
+            .into_iter()
+            .skip((page - 1) * COMPLETED_PER_PAGE)
+            .take(COMPLETED_PER_PAGE)
+            .collect(),
+    ))
+}
+
+#[get("/search?<q>&<list>")]
+fn search(
+    token: Option<Token>,
+    q: Option<String>,
+    list: Option<String>,
+    state: State<Users>,
+) -> Result<Json<Vec<SearchResult>>, Error> {
+    let token = authorised(token, Scope::TasksRead)?;
+    let query = q.as_deref().unwrap_or("");
+    let results = match list {
+        Some(name) => {
+            let store = find_list(&state, &token, Some(&name))?;
+            let store = store.lock().unwrap();
+            let documents = store
+                .search(query)
+                .into_iter()
+                .take(SEARCH_RESULTS_PER_LIST)
+                .cloned()
+                .collect();
+            vec![(name, documents)]
+        }
+        // NOTE(unwrap): Tokens always belong to an existing account
+        None => state
+            .get(token.username())
+            .unwrap()
+            .lists
+            .search(query, SEARCH_RESULTS_PER_LIST),
+    };
+
+    Ok(Json(
+        results
+            .into_iter()
+            .flat_map(|(list, documents)| {
+                documents.into_iter().map(move |document| SearchResult {
+                    list: list.clone(),
+                    document,
+                })
+            })
+            .collect(),
+    ))
+}
+
+impl From<&Task> for TaskBody {
+    fn from(task: &Task) -> Self {
+        TaskBody {
+            tags: task.tags(),
+            task: task.clone(),
+        }
+    }
+}
+
+/// The `Token` guard forwards when the Authorization header is missing and fails
+/// when it is invalid. Either way the API responds with a JSON error.
+pub(crate) fn authorised(token: Option<Token>, scope: Scope) -> Result<Token, Error> {
+    let token = token.ok_or_else(|| error(Status::Unauthorized, "missing or invalid API token"))?;
+    if token.allows(scope) {
+        Ok(token)
+    } else {
+        Err(error(
+            Status::Forbidden,
+            &format!("API token does not have the {} scope", scope),
+        ))
+    }
+}
+
+pub(crate) fn find_list(users: &Users, token: &Token, name: Option<&str>) -> Result<Store, Error> {
+    // NOTE(unwrap): Tokens always belong to an existing account
+    users
+        .get(token.username())
+        .unwrap()
+        .lists
+        .get(name.unwrap_or(DEFAULT_LIST))
+        .ok_or_else(|| error(Status::NotFound, "list not found"))
+}
+
+/// Distinguish a field that is present but `null` from one that is absent.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+fn parent_error() -> Error {
+    error(
+        Status::UnprocessableEntity,
+        "parent must be another task in the list, and subtasks are only one level deep",
+    )
+}
+
+fn parse_id(id: &str) -> Result<TaskId, Error> {
+    id.parse()
+        .map_err(|_| error(Status::BadRequest, "invalid task id"))
+}
+
+fn find_task(tasks: &[Task], id: TaskId) -> Result<&Task, Error> {
+    tasks
+        .iter()
+        .find(|task| task.id == id)
+        .ok_or_else(|| error(Status::NotFound, "task not found"))
+}
+
+fn json_error(err: JsonError) -> Error {
+    match err {
+        JsonError::Io(_) => error(Status::BadRequest, "unable to read request body"),
+        JsonError::Parse(_, err) => error(Status::UnprocessableEntity, &err.to_string()),
+    }
+}
+
+pub(crate) fn internal_error(message: &str, err: impl fmt::Display) -> Error {
+    log::error!("{}: {}", message, err);
+    error(Status::InternalServerError, message)
+}
+
+pub fn error(status: Status, message: &str) -> Error {
+    status::Custom(
+        status,
+        Json(ErrorBody {
+            error: message.to_string(),
+        }),
+    )
+}
@@ -1,6 +1,6 @@
 //! User authentication.
 
-use std::sync::Arc;
+use std::sync::{Arc, MutexGuard};
 
 use hyper::header::Header;
 use rocket::http::hyper::header::{Authorization, Bearer};
@@ -11,9 +11,16 @@ use rocket::response::{content, Flash, Redirect};
 use rocket::{Route, State};
 use time::Duration;
 
+use leaf::lists::DEFAULT_LIST;
+use leaf::tokens::{ApiToken, Scope, Scopes, TokenRegistry};
+use leaf::users::Users;
+
+use crate::form::{NewTokenForm, RevokeTokenForm};
 use crate::{config, tasks, templates};
 
 pub const LEAF_SESSION: &str = "LEAF_SESSION";
+/// The value of the session cookie before it held the username.
+const LEGACY_SESSION: &str = "1";
 
 pub type Config = Arc<config::Config>;
 
@@ -22,8 +29,13 @@ pub enum TokenError {
     Invalid,
 }
 
-pub struct User(usize);
-pub struct Token(String);
+/// A signed in user, identified by their username.
+pub struct User(String);
+/// An API token, along with the account it acts on behalf of.
+pub struct Token {
+    username: String,
+    scopes: Scopes,
+}
 pub enum UserOrToken {
     User(User),
     Token(Token),
@@ -31,18 +43,61 @@ pub enum UserOrToken {
 
 #[derive(FromForm)]
 struct Login {
+    username: Option<String>,
     password: String,
 }
 
+impl User {
+    pub fn username(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Token {
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(scope)
+    }
+
+    /// Find the token with the supplied secret, either the configured API token or
+    /// one created by an account.
+    pub fn authenticate(secret: &str, config: &Config, users: &Users) -> Option<Token> {
+        if config.api_token.as_deref() == Some(secret) {
+            return Some(Token {
+                username: users.owner().username.clone(),
+                scopes: Scopes::all(),
+            });
+        }
+
+        users.iter().find_map(|account| {
+            let mut tokens = account.tokens.lock().unwrap();
+            tokens.authenticate(secret).map(|token| Token {
+                username: account.username.clone(),
+                scopes: token.scopes.clone(),
+            })
+        })
+    }
+}
+
 impl<'a, 'r> FromRequest<'a, 'r> for User {
     type Error = std::convert::Infallible;
 
     fn from_request(request: &'a Request<'r>) -> request::Outcome<User, Self::Error> {
+        let users = request.guard::<State<Users>>().unwrap(); // NOTE(unwrap): Users should always be available
         request
             .cookies()
             .get_private(LEAF_SESSION)
-            .and_then(|cookie| cookie.value().parse().ok())
-            .map(|id| User(id))
+            .and_then(|cookie| match users.get(cookie.value()) {
+                Some(account) => Some(User(account.username.clone())),
+                // Sessions from before there were usernames belong to the only account
+                None if cookie.value() == LEGACY_SESSION && !users.is_multi_user() => {
+                    Some(User(users.owner().username.clone()))
+                }
+                None => None,
+            })
             .or_forward(())
     }
 }
@@ -57,16 +112,11 @@ impl<'a, 'r> FromRequest<'a, 'r> for Token {
             .headers()
             .get_one("Authorization")
             .and_then(|value| Header::parse_header(&[value.as_bytes().to_vec()]).ok())
-            .and_then(|token: Authorization<Bearer>| {
+            .map(|token: Authorization<Bearer>| {
                 let config = request.guard::<State<Config>>().unwrap(); // NOTE(unwrap): Config should always be available
-                if token.0.token == config.api_token {
-                    Some(Outcome::Success(Token(token.0.token)))
-                } else {
-                    Some(Outcome::Failure((
-                        Status::Unauthorized,
-                        TokenError::Invalid,
-                    )))
-                }
+                let users = request.guard::<State<Users>>().unwrap(); // NOTE(unwrap): Users should always be available
+                Token::authenticate(&token.0.token, &config, &users)
+                    .into_outcome((Status::Unauthorized, TokenError::Invalid))
             })
             .unwrap_or_else(|| Outcome::Forward(()))
     }
@@ -86,7 +136,15 @@ impl<'a, 'r> FromRequest<'a, 'r> for UserOrToken {
 }
 
 pub fn routes() -> Vec<Route> {
-    routes![login, logout, login_user, login_page]
+    routes![
+        login,
+        logout,
+        login_user,
+        login_page,
+        tokens,
+        create_token,
+        revoke_token
+    ]
 }
 
 #[post("/login", data = "<login>")]
@@ -94,9 +152,21 @@ fn login(
     mut cookies: Cookies,
     login: LenientForm<Login>,
     config: State<Config>,
+    users: State<Users>,
 ) -> Result<Redirect, Flash<Redirect>> {
-    if verify(&config.password_hash, login.password.as_bytes()) {
-        let cookie = Cookie::build(LEAF_SESSION, 1.to_string())
+    let account = if users.is_multi_user() {
+        login
+            .username
+            .as_deref()
+            .and_then(|username| users.get(username.trim()))
+    } else {
+        Some(users.owner())
+    };
+    let verified =
+        account.filter(|account| verify(&account.password_hash, login.password.as_bytes()));
+
+    if let Some(account) = verified {
+        let cookie = Cookie::build(LEAF_SESSION, account.username.clone())
             .path("/")
             .secure(config.secure_cookie)
             .http_only(true)
@@ -108,7 +178,11 @@ fn login(
     } else {
         Err(Flash::error(
             Redirect::to(uri!(login_page)),
-            "Invalid password.",
+            if users.is_multi_user() {
+                "Invalid username or password."
+            } else {
+                "Invalid password."
+            },
         ))
     }
 }
@@ -125,13 +199,116 @@ fn login_user(_user: User) -> Redirect {
 }
 
 #[get("/login", rank = 2)]
-pub fn login_page(flash: Option<FlashMessage>) -> content::Html<String> {
-    let page: templates::Layout<'_, '_, _> = templates::Layout {
+pub fn login_page(flash: Option<FlashMessage>, users: State<Users>) -> content::Html<String> {
+    let page: templates::Layout<'_, _> = templates::Layout {
         title: "Login",
         body: templates::Login {
             flash: flash.as_ref().map(|flash| flash.msg()),
+            multi_user: users.is_multi_user(),
         },
         user: None,
+        lists: &[],
+        list: None,
+    };
+    content::Html(page.to_string())
+}
+
+#[get("/tokens")]
+fn tokens(user: User, flash: Option<FlashMessage>, users: State<Users>) -> content::Html<String> {
+    let tokens = account_tokens(&users, &user);
+    token_page(
+        &user,
+        &users,
+        tokens.list(),
+        None,
+        flash.as_ref().map(|flash| flash.msg()),
+    )
+}
+
+/// Create a token. The response shows the secret, which is not stored, so this
+/// renders a page rather than redirecting.
+#[post("/tokens", data = "<form>")]
+fn create_token(
+    user: User,
+    form: LenientForm<NewTokenForm>,
+    users: State<Users>,
+) -> Result<content::Html<String>, Flash<Redirect>> {
+    let form = form.into_inner();
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Err(Flash::error(
+            Redirect::to(uri!(tokens)),
+            "Token name can't be blank",
+        ));
+    }
+    if form.scopes.is_empty() {
+        return Err(Flash::error(
+            Redirect::to(uri!(tokens)),
+            "Choose at least one scope",
+        ));
+    }
+
+    let mut tokens = account_tokens(&users, &user);
+    let secret = tokens.create(name, form.scopes).map_err(|err| {
+        log::error!("unable to create token: {}", err);
+        Flash::error(Redirect::to(uri!(tokens)), "Failed to create token")
+    })?;
+
+    Ok(token_page(
+        &user,
+        &users,
+        tokens.list(),
+        Some((name, &secret)),
+        None,
+    ))
+}
+
+#[post("/tokens/revoke", data = "<form>")]
+fn revoke_token(
+    user: User,
+    form: LenientForm<RevokeTokenForm>,
+    users: State<Users>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let mut tokens = account_tokens(&users, &user);
+    match tokens.revoke(&form.name) {
+        Ok(true) => Ok(Flash::success(
+            Redirect::to(uri!(tokens)),
+            format!("Revoked {}.", form.name),
+        )),
+        Ok(false) => Err(Flash::error(Redirect::to(uri!(tokens)), "Token not found")),
+        Err(err) => {
+            log::error!("unable to revoke token: {}", err);
+            Err(Flash::error(
+                Redirect::to(uri!(tokens)),
+                "Failed to revoke token",
+            ))
+        }
+    }
+}
+
+fn account_tokens<'a>(users: &'a Users, user: &User) -> MutexGuard<'a, TokenRegistry> {
+    // NOTE(unwrap): The User guard only succeeds for existing accounts
+    users.get(user.username()).unwrap().tokens.lock().unwrap()
+}
+
+fn token_page(
+    user: &User,
+    users: &Users,
+    tokens: &[ApiToken],
+    created: Option<(&str, &str)>,
+    flash: Option<&str>,
+) -> content::Html<String> {
+    let page: templates::Layout<'_, _> = templates::Layout {
+        title: "API Tokens",
+        body: templates::Tokens {
+            tokens,
+            created,
+            flash,
+            scopes: &Scope::ALL,
+        },
+        user: Some(user),
+        lists: &tasks::nav_lists(tasks::user_lists(users, user)),
+        list: Some(DEFAULT_LIST),
     };
     content::Html(page.to_string())
 }
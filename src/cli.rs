@@ -0,0 +1,395 @@
+//! Command line interface.
+//!
+//! Without a subcommand `leaf` runs the web server. The task subcommands operate
+//! directly on the task files, so they should not be used while the server is
+//! running as it will not see the changes.
+
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use argon2::{Config, Variant};
+use chrono::{Local, NaiveDate};
+use rand::Rng;
+
+use leaf::lists::{Lists, DEFAULT_LIST};
+use leaf::models::{NewTask, Store, TaskId};
+use leaf::quick_add;
+
+pub const USAGE: &str = "\
+Usage: leaf [--config PATH] [--user NAME] [COMMAND]
+
+Runs the web server when no command is given.
+
+Options:
+    --config PATH              Read settings from the TOML config file at PATH.
+                               Defaults to the value of LEAF_CONFIG, if set
+    --user NAME                Work with the tasks of the user NAME when
+                               LEAF_USERS_PATH is set. Defaults to the first
+                               user
+
+Commands:
+    add DESCRIPTION            Add a task. @DATE, !PRIORITY, and ^LIST in
+                               DESCRIPTION set its due date, priority, and list
+    list                       List tasks
+    complete ID...             Complete one or more tasks
+    completed [--since DATE]   List completed tasks, newest first. DATE is
+                               formatted YYYY-MM-DD
+    hash-password [OPTIONS]    Prompt for a password and print its hash for use
+                               as LEAF_PASSWORD_HASH
+    help                       Show this message
+
+Options for hash-password:
+    --argon2id                 Use argon2id instead of argon2i
+    --memory-cost KIB          Memory to use in KiB (default 4096)
+    --time-cost N              Number of iterations (default 3)";
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub config: Option<PathBuf>,
+    pub user: Option<String>,
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Add(String),
+    List,
+    Complete(Vec<TaskId>),
+    Completed { since: Option<NaiveDate> },
+    HashPassword(HashOptions),
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HashOptions {
+    pub variant: Variant,
+    pub mem_cost: u32,
+    pub time_cost: u32,
+}
+
+impl Default for HashOptions {
+    fn default() -> Self {
+        let config = Config::default();
+        HashOptions {
+            variant: config.variant,
+            mem_cost: config.mem_cost,
+            time_cost: config.time_cost,
+        }
+    }
+}
+
+/// Parse the command line arguments, excluding the program name.
+pub fn parse(mut args: &[String]) -> Result<Args, String> {
+    let mut config = None;
+    let mut user = None;
+    loop {
+        match args {
+            [flag, path, rest @ ..] if flag == "--config" => {
+                config = Some(PathBuf::from(path));
+                args = rest;
+            }
+            [flag, name, rest @ ..] if flag == "--user" => {
+                user = Some(name.to_string());
+                args = rest;
+            }
+            [flag] if flag == "--config" => return Err(String::from("--config expects a PATH")),
+            [flag] if flag == "--user" => return Err(String::from("--user expects a NAME")),
+            _ => break,
+        }
+    }
+
+    let command = parse_command(args)?;
+    if user.is_some() && command.is_none() {
+        return Err(String::from("--user only applies to commands"));
+    }
+    Ok(Args {
+        config,
+        user,
+        command,
+    })
+}
+
+/// Parse the subcommand and its arguments. Returns `None` if there is no
+/// subcommand.
+fn parse_command(args: &[String]) -> Result<Option<Command>, String> {
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), args),
+        None => return Ok(None),
+    };
+
+    let command = match command {
+        "add" => match args {
+            [description] if !description.trim().is_empty() => {
+                Command::Add(description.to_string())
+            }
+            [_] => return Err(String::from("description must not be empty")),
+            _ => return Err(String::from("add expects one argument: DESCRIPTION")),
+        },
+        "list" => {
+            no_arguments(command, args)?;
+            Command::List
+        }
+        "complete" => {
+            if args.is_empty() {
+                return Err(String::from("complete expects at least one ID"));
+            }
+            let ids = args
+                .iter()
+                .map(|id| {
+                    id.parse()
+                        .map_err(|_| format!("'{}' is not a valid task id", id))
+                })
+                .collect::<Result<_, _>>()?;
+            Command::Complete(ids)
+        }
+        "completed" => match args {
+            [] => Command::Completed { since: None },
+            [flag, date] if flag == "--since" => {
+                let since = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .map_err(|_| format!("'{}' is not a valid date (YYYY-MM-DD)", date))?;
+                Command::Completed { since: Some(since) }
+            }
+            _ => return Err(String::from("completed accepts only --since DATE")),
+        },
+        "hash-password" => Command::HashPassword(parse_hash_options(args)?),
+        "help" | "--help" | "-h" => Command::Help,
+        _ => return Err(format!("unknown command '{}'", command)),
+    };
+
+    Ok(Some(command))
+}
+
+fn parse_hash_options(args: &[String]) -> Result<HashOptions, String> {
+    let mut options = HashOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--argon2id" => options.variant = Variant::Argon2id,
+            "--memory-cost" => options.mem_cost = parse_cost(arg, args.next())?,
+            "--time-cost" => options.time_cost = parse_cost(arg, args.next())?,
+            _ => return Err(format!("unknown hash-password option '{}'", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_cost(flag: &str, value: Option<&String>) -> Result<u32, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .filter(|&cost| cost > 0)
+        .ok_or_else(|| format!("{} expects a positive number", flag))
+}
+
+fn no_arguments(command: &str, args: &[String]) -> Result<(), String> {
+    if args.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} does not take any arguments", command))
+    }
+}
+
+/// Run `command`. `open_lists` is only called for commands that need the task lists.
+pub fn run(
+    command: Command,
+    open_lists: impl FnOnce() -> Result<Lists, Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match command {
+        Command::HashPassword(options) => {
+            let password = rpassword::prompt_password_stderr("Password: ")?;
+            let confirm = rpassword::prompt_password_stderr("Confirm password: ")?;
+            if password != confirm {
+                return Err("passwords do not match".into());
+            }
+            writeln!(out, "{}", hash_password(password.as_bytes(), &options)?)?;
+        }
+        Command::Help => {
+            writeln!(out, "{}", USAGE)?;
+        }
+        Command::Add(description) => {
+            let task = quick_add::parse(&description, Local::today().naive_local());
+            if task.description.is_empty() {
+                return Err("description must not be empty".into());
+            }
+            let lists = open_lists()?;
+            let list = task.list.as_deref().unwrap_or(DEFAULT_LIST);
+            let store = lists
+                .get(list)
+                .ok_or_else(|| format!("no list named {}", list))?;
+            let id = store.lock().unwrap().add(NewTask::from(task))?;
+            writeln!(out, "{}", id)?;
+        }
+        Command::List => {
+            let store = default_list(&open_lists()?);
+            let store = store.lock().unwrap();
+            for task in store.list() {
+                writeln!(out, "{}\t{}", task.id, task.description)?;
+            }
+        }
+        Command::Complete(ids) => {
+            let store = default_list(&open_lists()?);
+            let mut store = store.lock().unwrap();
+            if let Some(id) = ids
+                .iter()
+                .find(|&&id| !store.all().iter().any(|task| task.id == id))
+            {
+                return Err(format!("no task with id {}", id).into());
+            }
+            store.complete(&ids)?;
+        }
+        Command::Completed { since } => {
+            let store = default_list(&open_lists()?);
+            let completed = store.lock().unwrap().completed()?;
+            let tasks = completed.iter().filter(|task| match since {
+                Some(since) => task.completed_at.naive_utc().date() >= since,
+                None => true,
+            });
+            for task in tasks {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}",
+                    task.completed_at.to_rfc3339(),
+                    task.id,
+                    task.description
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn default_list(lists: &Lists) -> Store {
+    // NOTE(unwrap): The default list always exists
+    lists.get(DEFAULT_LIST).unwrap()
+}
+
+/// Hash `password` with a random salt, returning the encoded hash.
+fn hash_password(password: &[u8], options: &HashOptions) -> Result<String, argon2::Error> {
+    let salt: [u8; 16] = rand::thread_rng().gen();
+    let config = Config {
+        variant: options.variant,
+        mem_cost: options.mem_cost,
+        time_cost: options.time_cost,
+        ..Config::default()
+    };
+    argon2::hash_encoded(password, &salt, &config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_command(&[]), Ok(None));
+        assert_eq!(
+            parse_command(&args(&["add", "Read a post"])),
+            Ok(Some(Command::Add(String::from("Read a post"))))
+        );
+        assert_eq!(parse_command(&args(&["list"])), Ok(Some(Command::List)));
+        assert_eq!(
+            parse_command(&args(&["complete", "01E5S5SZ0Z7GDTCSRS0EG7SG9N"])),
+            Ok(Some(Command::Complete(vec!["01E5S5SZ0Z7GDTCSRS0EG7SG9N"
+                .parse()
+                .unwrap()])))
+        );
+        assert_eq!(
+            parse_command(&args(&["completed", "--since", "2026-01-01"])),
+            Ok(Some(Command::Completed {
+                since: Some(NaiveDate::from_ymd(2026, 1, 1))
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_hash_password() {
+        assert_eq!(
+            parse_command(&args(&["hash-password"])),
+            Ok(Some(Command::HashPassword(HashOptions::default())))
+        );
+        assert_eq!(
+            parse_command(&args(&[
+                "hash-password",
+                "--argon2id",
+                "--memory-cost",
+                "65536",
+                "--time-cost",
+                "2"
+            ])),
+            Ok(Some(Command::HashPassword(HashOptions {
+                variant: Variant::Argon2id,
+                mem_cost: 65536,
+                time_cost: 2,
+            })))
+        );
+        assert!(parse_command(&args(&["hash-password", "--time-cost"])).is_err());
+        assert!(parse_command(&args(&["hash-password", "--memory-cost", "0"])).is_err());
+        assert!(parse_command(&args(&["hash-password", "--salt", "abc"])).is_err());
+    }
+
+    #[test]
+    fn test_hash_password() {
+        let options = HashOptions {
+            variant: Variant::Argon2id,
+            mem_cost: 1024,
+            time_cost: 1,
+        };
+        let hash = hash_password(b"secret", &options).unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(argon2::verify_encoded(&hash, b"secret").unwrap());
+        assert_ne!(hash, hash_password(b"secret", &options).unwrap());
+    }
+
+    #[test]
+    fn test_parse_config() {
+        assert_eq!(
+            parse(&args(&["--config", "leaf.toml", "list"])),
+            Ok(Args {
+                config: Some(PathBuf::from("leaf.toml")),
+                user: None,
+                command: Some(Command::List),
+            })
+        );
+        assert_eq!(
+            parse(&args(&["--config", "leaf.toml"])),
+            Ok(Args {
+                config: Some(PathBuf::from("leaf.toml")),
+                user: None,
+                command: None,
+            })
+        );
+        assert!(parse(&args(&["--config"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_user() {
+        assert_eq!(
+            parse(&args(&["--user", "alice", "--config", "leaf.toml", "list"])),
+            Ok(Args {
+                config: Some(PathBuf::from("leaf.toml")),
+                user: Some(String::from("alice")),
+                command: Some(Command::List),
+            })
+        );
+        assert!(parse(&args(&["--user"])).is_err());
+        assert!(parse(&args(&["--user", "alice"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_command(&args(&["add"])).is_err());
+        assert!(parse_command(&args(&["add", " "])).is_err());
+        assert!(parse_command(&args(&["list", "extra"])).is_err());
+        assert!(parse_command(&args(&["complete"])).is_err());
+        assert!(parse_command(&args(&["complete", "nope"])).is_err());
+        assert!(parse_command(&args(&["completed", "--since", "January"])).is_err());
+        assert!(parse_command(&args(&["frobnicate"])).is_err());
+    }
+}
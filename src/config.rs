@@ -1,39 +1,412 @@
+//! Configuration.
+//!
+//! Settings are read from an optional TOML config file and environment variables.
+//! Environment variables take precedence over values in the file.
+
 use std::env;
 use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
 
+pub const LEAF_CONFIG: &str = "LEAF_CONFIG";
 const LEAF_API_TOKEN: &str = "LEAF_API_TOKEN";
 const LEAF_PASSWORD_HASH: &str = "LEAF_PASSWORD_HASH";
 const LEAF_SECURE_COOKIE: &str = "LEAF_SECURE_COOKIE";
+pub const LEAF_USERS_PATH: &str = "LEAF_USERS_PATH";
+const LEAF_TASKS_PATH: &str = "LEAF_TASKS_PATH";
+const LEAF_COMPLETED_PATH: &str = "LEAF_COMPLETED_PATH";
+const LEAF_DISCARDED_PATH: &str = "LEAF_DISCARDED_PATH";
+const LEAF_DATABASE_PATH: &str = "LEAF_DATABASE_PATH";
+const LEAF_LISTS_PATH: &str = "LEAF_LISTS_PATH";
+const LEAF_TOKENS_PATH: &str = "LEAF_TOKENS_PATH";
+const ROCKET_SECRET_KEY: &str = "ROCKET_SECRET_KEY";
 const MIN_TOKEN_LEN: usize = 64;
 
 pub struct Config {
-    pub password_hash: String,
-    pub api_token: String,
+    /// Password hash of the single account. Not used when `users_path` is set.
+    pub password_hash: Option<String>,
+    /// Directory holding multiple user accounts.
+    pub users_path: Option<PathBuf>,
+    /// Token with all scopes, in addition to those in the token registry.
+    pub api_token: Option<String>,
     pub secure_cookie: bool,
+    pub tasks_path: PathBuf,
+    pub completed_path: PathBuf,
+    pub discarded_path: Option<PathBuf>,
+    pub database_path: PathBuf,
+    pub lists_path: Option<PathBuf>,
+    pub tokens_path: PathBuf,
+    /// Rocket's secret key, used to encrypt the login cookie.
+    pub secret_key: Option<String>,
+}
+
+/// The contents of the config file. All settings are optional.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    password_hash: Option<String>,
+    password_hash_file: Option<PathBuf>,
+    users_path: Option<PathBuf>,
+    api_token: Option<String>,
+    api_token_file: Option<PathBuf>,
+    secure_cookie: Option<bool>,
+    tasks_path: Option<PathBuf>,
+    completed_path: Option<PathBuf>,
+    discarded_path: Option<PathBuf>,
+    database_path: Option<PathBuf>,
+    lists_path: Option<PathBuf>,
+    tokens_path: Option<PathBuf>,
+    rocket: Option<RocketFile>,
+}
+
+/// The `[rocket]` table of the config file. Only the secret key can be set here,
+/// other Rocket settings come from `ROCKET_*` environment variables.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RocketFile {
+    secret_key: Option<String>,
+    secret_key_file: Option<PathBuf>,
+}
+
+/// Where a setting came from, for error messages.
+enum Source<'a> {
+    Env(String),
+    File(&'a Path, String),
+}
+
+/// Collects problems with the configuration so they can be reported together.
+struct Errors<'a> {
+    path: Option<&'a Path>,
+    errors: Vec<String>,
 }
 
 impl Config {
-    pub fn from_env() -> Result<Self, String> {
-        let password_hash = env::var(LEAF_PASSWORD_HASH)
-            .map_err(|_| format!("{} is missing or invalid", LEAF_PASSWORD_HASH))?;
-        let api_token = env::var(LEAF_API_TOKEN)
-            .map_err(|_| format!("{} is missing or invalid", LEAF_API_TOKEN))?;
-        if api_token.len() < MIN_TOKEN_LEN {
-            return Err(format!(
-                "{} is too short. At least {} chars required but got {} ",
-                LEAF_API_TOKEN,
-                MIN_TOKEN_LEN,
-                api_token.len()
-            ));
+    /// Load the configuration from the config file at `path`, if any, and the
+    /// environment.
+    ///
+    /// The error lists every problem found, along with where the offending value
+    /// came from.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let mut errors = Errors {
+            path,
+            errors: Vec::new(),
+        };
+        let file = match path {
+            Some(path) => read_file(path).unwrap_or_else(|err| {
+                errors.push(err);
+                ConfigFile::default()
+            }),
+            None => ConfigFile::default(),
+        };
+
+        let password_hash = secret_setting(
+            &mut errors,
+            LEAF_PASSWORD_HASH,
+            "password_hash",
+            file.password_hash,
+            file.password_hash_file,
+        );
+        let api_token = secret_setting(
+            &mut errors,
+            LEAF_API_TOKEN,
+            "api_token",
+            file.api_token,
+            file.api_token_file,
+        );
+        if let Some((ref api_token, ref source)) = api_token {
+            if api_token.len() < MIN_TOKEN_LEN {
+                errors.push(format!(
+                    "{} is too short. At least {} chars required but got {}",
+                    source,
+                    MIN_TOKEN_LEN,
+                    api_token.len()
+                ));
+            }
+        }
+        let secure_cookie = match env::var_os(LEAF_SECURE_COOKIE) {
+            Some(value) => value != OsStr::new("false"),
+            None => file.secure_cookie.unwrap_or(true),
+        };
+
+        let rocket = file.rocket.unwrap_or_default();
+        let secret_key = secret_setting(
+            &mut errors,
+            ROCKET_SECRET_KEY,
+            "rocket.secret_key",
+            rocket.secret_key,
+            rocket.secret_key_file,
+        );
+
+        if !errors.errors.is_empty() {
+            return Err(errors.report());
         }
-        let secure_cookie = env::var_os(LEAF_SECURE_COOKIE)
-            .map(|value| value != OsStr::new("false"))
-            .unwrap_or(true);
 
         Ok(Config {
-            password_hash,
-            api_token,
+            password_hash: password_hash.map(|(value, _)| value),
+            users_path: path_setting(LEAF_USERS_PATH, file.users_path),
+            api_token: api_token.map(|(value, _)| value),
             secure_cookie,
+            tasks_path: path_setting(LEAF_TASKS_PATH, file.tasks_path)
+                .unwrap_or_else(|| PathBuf::from("tasks.csv")),
+            completed_path: path_setting(LEAF_COMPLETED_PATH, file.completed_path)
+                .unwrap_or_else(|| PathBuf::from("completed.csv")),
+            discarded_path: path_setting(LEAF_DISCARDED_PATH, file.discarded_path),
+            database_path: path_setting(LEAF_DATABASE_PATH, file.database_path)
+                .unwrap_or_else(|| PathBuf::from("leaf.sqlite")),
+            lists_path: path_setting(LEAF_LISTS_PATH, file.lists_path),
+            tokens_path: path_setting(LEAF_TOKENS_PATH, file.tokens_path)
+                .unwrap_or_else(|| PathBuf::from("tokens.csv")),
+            secret_key: secret_key.map(|(value, _)| value),
         })
     }
+
+    /// Check the settings required to run the server are present.
+    pub fn check_server(&self) -> Result<(), String> {
+        if self.password_hash.is_none() && self.users_path.is_none() {
+            Err(format!(
+                "- Neither {} (password_hash in the config file) nor {} (users_path) is set",
+                LEAF_PASSWORD_HASH, LEAF_USERS_PATH
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Errors<'_> {
+    fn push(&mut self, error: String) {
+        self.errors.push(error)
+    }
+
+    fn report(&self) -> String {
+        let errors = self
+            .errors
+            .iter()
+            .map(|err| format!("- {}", err))
+            .collect::<Vec<_>>();
+        let file = match self.path {
+            Some(path) => format!("the config file ({})", path.display()),
+            None => format!("a config file if supplied with --config or {}", LEAF_CONFIG),
+        };
+        format!(
+            "{}\n\nSettings are read from environment variables first, then {}.",
+            errors.join("\n"),
+            file
+        )
+    }
+}
+
+fn read_file(path: &Path) -> Result<ConfigFile, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Unable to read config file {}: {}", path.display(), err))?;
+    toml::from_str(&contents)
+        .map_err(|err| format!("Invalid config file {}: {}", path.display(), err))
+}
+
+/// Read a secret setting. The value can be supplied directly or read from a file,
+/// so that it doesn't have to be in the environment. In order of precedence it
+/// comes from:
+///
+/// 1. The environment variable `name`.
+/// 2. The file named by the environment variable `{name}_FILE`.
+/// 3. `file_value`, read from `key` in the config file.
+/// 4. The file named by `file_path`, read from `{key}_file` in the config file.
+fn secret_setting<'a>(
+    errors: &mut Errors<'a>,
+    name: &str,
+    key: &str,
+    file_value: Option<String>,
+    file_path: Option<PathBuf>,
+) -> Option<(String, Source<'a>)> {
+    let env_value = env_string(errors, name);
+    let name_file = format!("{}_FILE", name);
+    let env_path = env::var_os(&name_file);
+    if env_value.is_some() && env_path.is_some() {
+        errors.push(format!(
+            "Only one of {} and {} may be set",
+            Source::Env(name.to_string()),
+            Source::Env(name_file)
+        ));
+        return None;
+    }
+    if let Some(value) = env_value {
+        return Some((value, Source::Env(name.to_string())));
+    }
+    if let Some(path) = env_path {
+        return read_secret(errors, Path::new(&path), Source::Env(name_file));
+    }
+
+    let config_path = errors.path?;
+    let key_file = format!("{}_file", key);
+    match (file_value, file_path) {
+        (Some(_), Some(_)) => {
+            errors.push(format!(
+                "Only one of {} and {} may be set",
+                Source::File(config_path, key.to_string()),
+                Source::File(config_path, key_file)
+            ));
+            None
+        }
+        (Some(value), None) => Some((value, Source::File(config_path, key.to_string()))),
+        (None, Some(path)) => read_secret(errors, &path, Source::File(config_path, key_file)),
+        (None, None) => None,
+    }
+}
+
+fn env_string(errors: &mut Errors, name: &str) -> Option<String> {
+    let value = env::var_os(name)?;
+    match value.into_string() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            errors.push(format!(
+                "{} is not valid UTF-8",
+                Source::Env(name.to_string())
+            ));
+            None
+        }
+    }
+}
+
+/// Read a secret from the file at `path`, which was named by `source`. Surrounding
+/// whitespace, such as a trailing newline, is removed.
+fn read_secret<'a>(
+    errors: &mut Errors,
+    path: &Path,
+    source: Source<'a>,
+) -> Option<(String, Source<'a>)> {
+    match fs::read_to_string(path) {
+        Ok(contents) if contents.trim().is_empty() => {
+            errors.push(format!("{} named by {} is empty", path.display(), source));
+            None
+        }
+        Ok(contents) => Some((contents.trim().to_string(), source)),
+        Err(err) => {
+            errors.push(format!(
+                "Unable to read {} named by {}: {}",
+                path.display(),
+                source,
+                err
+            ));
+            None
+        }
+    }
+}
+
+fn path_setting(name: &str, file_value: Option<PathBuf>) -> Option<PathBuf> {
+    env::var_os(name).map(PathBuf::from).or(file_value)
+}
+
+impl fmt::Display for Source<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Env(name) => write!(f, "{} (environment variable)", name),
+            Source::File(path, key) => write!(f, "{} (in {})", key, path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_file() {
+        let file: ConfigFile = toml::from_str(
+            r#"
+            password_hash = "$argon2i$v=19$m=4096,t=3,p=1$c2FsdHNhbHQ$DKlexoEJUoZTmkAAC3SaMWk30El9/RvVhlqGo6afIng"
+            secure_cookie = false
+            tasks_path = "/var/lib/leaf/tasks.csv"
+
+            [rocket]
+            secret_key_file = "/etc/leaf/secret_key"
+            "#,
+        )
+        .unwrap();
+        assert!(file.password_hash.is_some());
+        assert_eq!(file.secure_cookie, Some(false));
+        assert_eq!(
+            file.tasks_path,
+            Some(PathBuf::from("/var/lib/leaf/tasks.csv"))
+        );
+        assert_eq!(
+            file.rocket.unwrap().secret_key_file,
+            Some(PathBuf::from("/etc/leaf/secret_key"))
+        );
+        assert!(toml::from_str::<ConfigFile>("[rocket]\nport = 8080").is_err());
+    }
+
+    #[test]
+    fn test_secret_setting_file() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let config_path = testdir.path().join("leaf.toml");
+        let secret_path = testdir.path().join("token");
+        fs::write(&secret_path, "secret\n").unwrap();
+
+        let mut errors = Errors {
+            path: Some(&config_path),
+            errors: Vec::new(),
+        };
+        let (value, source) = secret_setting(
+            &mut errors,
+            "LEAF_TEST_UNSET",
+            "api_token",
+            None,
+            Some(secret_path.clone()),
+        )
+        .unwrap();
+        assert_eq!(value, "secret");
+        assert_eq!(
+            source.to_string(),
+            format!("api_token_file (in {})", config_path.display())
+        );
+
+        assert!(secret_setting(
+            &mut errors,
+            "LEAF_TEST_UNSET",
+            "api_token",
+            Some(String::from("secret")),
+            Some(secret_path),
+        )
+        .is_none());
+        assert!(secret_setting(
+            &mut errors,
+            "LEAF_TEST_UNSET",
+            "api_token",
+            None,
+            Some(testdir.path().join("missing")),
+        )
+        .is_none());
+        assert_eq!(errors.errors.len(), 2);
+    }
+
+    #[test]
+    fn test_config_file_unknown_key() {
+        assert!(toml::from_str::<ConfigFile>("pasword_hash = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let path = Path::new("leaf.toml");
+        let mut errors = Errors {
+            path: Some(path),
+            errors: Vec::new(),
+        };
+        errors.push(format!(
+            "{} is too short",
+            Source::File(path, String::from("api_token"))
+        ));
+        errors.push(format!(
+            "{} is not valid UTF-8",
+            Source::Env(String::from(LEAF_PASSWORD_HASH))
+        ));
+        assert_eq!(
+            errors.report(),
+            "- api_token (in leaf.toml) is too short\n\
+             - LEAF_PASSWORD_HASH (environment variable) is not valid UTF-8\n\n\
+             Settings are read from environment variables first, then the config file (leaf.toml)."
+        );
+    }
 }
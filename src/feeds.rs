@@ -0,0 +1,261 @@
+//! Atom feeds.
+//!
+//! Feeds of the active and recently completed tasks in a list, for feed readers and
+//! other tools that consume them. They require an API token with the `tasks:read`
+//! scope, supplied as a Bearer token or, for readers that can't set headers, in the
+//! `token` query parameter. Errors are returned like those of the API. The URLs in a
+//! task become the links of its entry.
+
+use std::collections::BTreeSet;
+
+use chrono::Utc;
+use rocket::http::uri::Origin;
+use rocket::http::ContentType;
+use rocket::request::LenientForm;
+use rocket::response::content::Content;
+use rocket::{Route, State};
+use sha2::{Digest, Sha256};
+
+use leaf::lists::DEFAULT_LIST;
+use leaf::models::{CompletedTask, Task, TaskId, Timestamp};
+use leaf::tags;
+use leaf::tokens::Scope;
+use leaf::users::Users;
+
+use crate::api::{self, authorised, find_list};
+use crate::auth::{Config, Token};
+use crate::form::TaskFilter;
+use crate::templates;
+
+/// The number of recently completed tasks in the completed feed.
+pub const COMPLETED_FEED_ENTRIES: usize = 50;
+
+/// A task as an entry in a feed.
+pub struct Entry<'a> {
+    pub id: String,
+    pub title: &'a str,
+    /// The notes of the task, or its description if it has none.
+    pub content: &'a str,
+    pub published: Timestamp,
+    pub updated: Timestamp,
+    pub tags: BTreeSet<String>,
+    pub links: Vec<&'a str>,
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![tasks, completed]
+}
+
+#[get("/feeds/tasks.atom?<token>&<list>&<filter..>")]
+fn tasks(
+    auth: Option<Token>,
+    token: Option<String>,
+    list: Option<String>,
+    filter: LenientForm<TaskFilter>,
+    uri: &Origin,
+    config: State<Config>,
+    users: State<Users>,
+) -> Result<Content<String>, api::Error> {
+    let token = authorised(
+        with_query_token(auth, token, &config, &users),
+        Scope::TasksRead,
+    )?;
+    let store = find_list(&users, &token, list.as_deref())?;
+    let store = store.lock().unwrap();
+    let tasks = if filter.snoozed {
+        store.snoozed()
+    } else {
+        store.list()
+    };
+    let entries = tasks
+        .into_iter()
+        .filter(|task| filter.matches(task))
+        .map(Entry::from)
+        .collect::<Vec<_>>();
+
+    Ok(feed("Tasks", list.as_deref(), &token, uri, &entries))
+}
+
+#[get("/feeds/completed.atom?<token>&<list>")]
+fn completed(
+    auth: Option<Token>,
+    token: Option<String>,
+    list: Option<String>,
+    uri: &Origin,
+    config: State<Config>,
+    users: State<Users>,
+) -> Result<Content<String>, api::Error> {
+    let token = authorised(
+        with_query_token(auth, token, &config, &users),
+        Scope::TasksRead,
+    )?;
+    let store = find_list(&users, &token, list.as_deref())?;
+    let completed = store
+        .lock()
+        .unwrap()
+        .completed()
+        .map_err(|err| api::internal_error("Failed to read completed tasks", err))?;
+    let entries = completed
+        .iter()
+        .take(COMPLETED_FEED_ENTRIES)
+        .map(Entry::from)
+        .collect::<Vec<_>>();
+
+    Ok(feed("Completed", list.as_deref(), &token, uri, &entries))
+}
+
+fn feed(
+    title: &str,
+    list: Option<&str>,
+    token: &Token,
+    uri: &Origin,
+    entries: &[Entry],
+) -> Content<String> {
+    let list = list.unwrap_or(DEFAULT_LIST);
+    let title = if list == DEFAULT_LIST {
+        format!("{} – Leaf", title)
+    } else {
+        format!("{} in {} – Leaf", title, list)
+    };
+    // Leaf doesn't know the host it is served from, so the id is derived from the
+    // account and the URL without the token, which stay the same for the feed
+    let self_url = self_url(uri);
+    let id = Sha256::digest(format!("{}:{}", token.username(), self_url).as_bytes());
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&id[..16]);
+    let updated = entries
+        .iter()
+        .map(|entry| entry.updated)
+        .max()
+        .unwrap_or_else(Utc::now);
+
+    let body = templates::Feed {
+        id: &uuid_urn(u128::from_be_bytes(bytes)),
+        title: &title,
+        self_url: &self_url,
+        author: token.username(),
+        updated,
+        entries,
+    };
+    Content(
+        ContentType::new("application", "atom+xml"),
+        body.to_string(),
+    )
+}
+
+/// The token from the guard, which authenticates a Bearer token, or else the one
+/// with the secret from the `token` query parameter.
+fn with_query_token(
+    auth: Option<Token>,
+    secret: Option<String>,
+    config: &Config,
+    users: &Users,
+) -> Option<Token> {
+    auth.or_else(|| secret.and_then(|secret| Token::authenticate(&secret, config, users)))
+}
+
+/// The URL of the feed without the token, so that it isn't shared along with the
+/// feed.
+fn self_url(uri: &Origin) -> String {
+    let query = uri
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|param| !param.is_empty() && !param.starts_with("token="))
+        .collect::<Vec<_>>();
+    if query.is_empty() {
+        uri.path().to_string()
+    } else {
+        format!("{}?{}", uri.path(), query.join("&"))
+    }
+}
+
+fn uuid_urn(value: u128) -> String {
+    format!(
+        "urn:uuid:{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        value >> 96,
+        (value >> 80) & 0xffff,
+        (value >> 64) & 0xffff,
+        (value >> 48) & 0xffff,
+        value & 0xffff_ffff_ffff
+    )
+}
+
+fn entry_id(id: TaskId) -> String {
+    uuid_urn(u128::from(id))
+}
+
+impl<'a> From<&'a Task> for Entry<'a> {
+    fn from(task: &'a Task) -> Self {
+        Entry {
+            id: entry_id(task.id),
+            title: &task.description,
+            content: task.notes.as_deref().unwrap_or(&task.description),
+            published: task.created_at,
+            updated: task.created_at,
+            tags: task.tags(),
+            links: links(&task.description, task.notes.as_deref()),
+        }
+    }
+}
+
+impl<'a> From<&'a CompletedTask> for Entry<'a> {
+    fn from(task: &'a CompletedTask) -> Self {
+        Entry {
+            id: entry_id(task.id),
+            title: &task.description,
+            content: task.notes.as_deref().unwrap_or(&task.description),
+            published: task.created_at,
+            updated: task.completed_at,
+            tags: tags::parse(&task.description),
+            links: links(&task.description, task.notes.as_deref()),
+        }
+    }
+}
+
+/// The URLs in the description followed by any others in the notes.
+fn links<'a>(description: &'a str, notes: Option<&'a str>) -> Vec<&'a str> {
+    let mut links = templates::urls(description);
+    for url in notes.map(templates::urls).unwrap_or_default() {
+        if !links.contains(&url) {
+            links.push(url);
+        }
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uuid_urn() {
+        assert_eq!(
+            uuid_urn(0x0123_4567_89ab_cdef_0011_2233_4455_6677),
+            "urn:uuid:01234567-89ab-cdef-0011-223344556677"
+        );
+    }
+
+    #[test]
+    fn test_self_url() {
+        let url = |uri| self_url(&Origin::parse(uri).unwrap());
+        assert_eq!(url("/feeds/tasks.atom"), "/feeds/tasks.atom");
+        assert_eq!(url("/feeds/tasks.atom?token=secret"), "/feeds/tasks.atom");
+        assert_eq!(
+            url("/feeds/tasks.atom?list=reading&token=secret&tag=rust"),
+            "/feeds/tasks.atom?list=reading&tag=rust"
+        );
+    }
+
+    #[test]
+    fn test_links() {
+        assert_eq!(
+            links(
+                "Read https://example.com/a #reading",
+                Some("Via https://example.org/\nhttps://example.com/a")
+            ),
+            vec!["https://example.com/a", "https://example.org/"]
+        );
+        assert!(links("No links", None).is_empty());
+    }
+}
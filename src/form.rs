@@ -1,10 +1,40 @@
-use rocket::request::{FormItems, FromForm};
+use chrono::{Local, NaiveDate};
+use rocket::http::RawStr;
+use rocket::request::{FormItems, FromForm, FromFormValue};
 
-use leaf::models::TaskId;
+use leaf::models::{Position, Priority, Task, TaskId};
+use leaf::tokens::Scopes;
 
 pub struct TasksForm {
     pub new_task: Option<String>,
+    pub due: Option<NaiveDate>,
+    /// The task to add the new task as a subtask of.
+    pub parent: Option<TaskId>,
     pub completed_ids: Vec<TaskId>,
+    /// A task to move, from one of the move buttons.
+    pub move_task: Option<(TaskId, Position)>,
+    /// A task to snooze until a date, or show again if there's no date.
+    pub snooze: Option<(TaskId, Option<NaiveDate>)>,
+}
+
+/// An optional date from a date input, which is submitted empty when no date is
+/// chosen.
+pub struct OptionalDate(pub Option<NaiveDate>);
+
+/// Filters for the task list, from the query string.
+#[derive(FromForm, Default)]
+pub struct TaskFilter {
+    pub due: Option<DueFilter>,
+    pub tag: Option<String>,
+    /// Show snoozed tasks instead of the tasks that are visible.
+    pub snoozed: bool,
+}
+
+/// Filter for tasks by due date, from the `due` query parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DueFilter {
+    /// Tasks due today, or overdue.
+    Today,
 }
 
 impl<'f> FromForm<'f> for TasksForm {
@@ -13,7 +43,11 @@ impl<'f> FromForm<'f> for TasksForm {
 
     fn from_form(items: &mut FormItems<'f>, strict: bool) -> Result<TasksForm, ()> {
         let mut description = None;
+        let mut due = None;
+        let mut parent = None;
         let mut completed_ids = Vec::new();
+        let mut move_task = None;
+        let mut snooze = None;
 
         for item in items {
             match item.key.as_str() {
@@ -23,6 +57,21 @@ impl<'f> FromForm<'f> for TasksForm {
                         description = Some(decoded);
                     }
                 }
+                "due" if due.is_none() => {
+                    due = OptionalDate::from_form_value(item.value).map_err(|_| ())?.0;
+                }
+                "parent" if parent.is_none() => {
+                    let value = item.value.url_decode().map_err(|_| ())?;
+                    parent = Some(value.parse().map_err(|_| ())?);
+                }
+                "move" if move_task.is_none() => {
+                    let value = item.value.url_decode().map_err(|_| ())?;
+                    move_task = Some(parse_move(&value)?);
+                }
+                "snooze" if snooze.is_none() => {
+                    let value = item.value.url_decode().map_err(|_| ())?;
+                    snooze = Some(parse_snooze(&value)?);
+                }
                 key if key.starts_with("complete") => {
                     let id = item
                         .value
@@ -38,7 +87,217 @@ impl<'f> FromForm<'f> for TasksForm {
 
         Ok(TasksForm {
             new_task: description,
+            due,
+            parent,
             completed_ids,
+            move_task,
+            snooze,
         })
     }
 }
+
+/// Parse a move button value: `ID:top`, `ID:bottom`, `ID:before:OTHER_ID`, or
+/// `ID:after:OTHER_ID`.
+fn parse_move(value: &str) -> Result<(TaskId, Position), ()> {
+    let parts = value.split(':').collect::<Vec<_>>();
+    let parse_id = |id: &str| id.parse::<TaskId>().map_err(|_| ());
+    let position = match parts[1..] {
+        ["top"] => Position::Top,
+        ["bottom"] => Position::Bottom,
+        ["before", other] => Position::Before(parse_id(other)?),
+        ["after", other] => Position::After(parse_id(other)?),
+        _ => return Err(()),
+    };
+    Ok((parse_id(parts[0])?, position))
+}
+
+/// Parse a snooze button value: `ID:YYYY-MM-DD`, or `ID:` to show the task again.
+fn parse_snooze(value: &str) -> Result<(TaskId, Option<NaiveDate>), ()> {
+    let mut parts = value.splitn(2, ':');
+    let id = parts.next().unwrap_or("").parse().map_err(|_| ())?;
+    let until = match parts.next().ok_or(())? {
+        "" => None,
+        date => Some(NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| ())?),
+    };
+    Ok((id, until))
+}
+
+#[derive(FromForm)]
+pub struct EditTaskForm {
+    pub description: String,
+    pub due: OptionalDate,
+    pub priority: Option<FormPriority>,
+    pub notes: Option<String>,
+    pub recurrence: Option<String>,
+    pub hidden_until: Option<OptionalDate>,
+    pub parent: Option<OptionalTaskId>,
+}
+
+pub struct FormPriority(pub Priority);
+
+/// An optional task id from a select, which is submitted empty for no task.
+pub struct OptionalTaskId(pub Option<TaskId>);
+
+impl<'v> FromFormValue<'v> for OptionalDate {
+    type Error = &'v RawStr;
+
+    fn from_form_value(value: &'v RawStr) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Ok(OptionalDate(None));
+        }
+        NaiveDate::parse_from_str(value.as_str(), "%Y-%m-%d")
+            .map(|date| OptionalDate(Some(date)))
+            .map_err(|_| value)
+    }
+
+    fn default() -> Option<Self> {
+        Some(OptionalDate(None))
+    }
+}
+
+impl<'v> FromFormValue<'v> for FormPriority {
+    type Error = &'v RawStr;
+
+    fn from_form_value(value: &'v RawStr) -> Result<Self, Self::Error> {
+        value.as_str().parse().map(FormPriority).map_err(|_| value)
+    }
+}
+
+impl<'v> FromFormValue<'v> for OptionalTaskId {
+    type Error = &'v RawStr;
+
+    fn from_form_value(value: &'v RawStr) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Ok(OptionalTaskId(None));
+        }
+        value
+            .as_str()
+            .parse()
+            .map(|id| OptionalTaskId(Some(id)))
+            .map_err(|_| value)
+    }
+}
+
+impl TaskFilter {
+    /// The tag to filter by, without a leading `#`.
+    pub fn tag(&self) -> Option<&str> {
+        self.tag
+            .as_deref()
+            .map(|tag| tag.trim_start_matches('#'))
+            .filter(|tag| !tag.is_empty())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.due.is_none() && self.tag().is_none() && !self.snoozed
+    }
+
+    pub fn matches(&self, task: &Task) -> bool {
+        self.due.map_or(true, |due| due.matches(task))
+            && self.tag().map_or(true, |tag| task.has_tag(tag))
+    }
+}
+
+impl DueFilter {
+    pub fn matches(&self, task: &Task) -> bool {
+        match self {
+            DueFilter::Today => {
+                let today = Local::today().naive_local();
+                task.is_due_today(today) || task.is_overdue(today)
+            }
+        }
+    }
+}
+
+impl<'v> FromFormValue<'v> for DueFilter {
+    type Error = &'v RawStr;
+
+    fn from_form_value(value: &'v RawStr) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "today" => Ok(DueFilter::Today),
+            _ => Err(value),
+        }
+    }
+}
+
+#[derive(FromForm)]
+pub struct DiscardTaskForm {
+    pub reason: Option<String>,
+}
+
+/// A form containing one or more `id` fields.
+pub struct TaskIdsForm {
+    pub ids: Vec<TaskId>,
+}
+
+impl<'f> FromForm<'f> for TaskIdsForm {
+    type Error = ();
+
+    fn from_form(items: &mut FormItems<'f>, strict: bool) -> Result<TaskIdsForm, ()> {
+        let mut ids = Vec::new();
+
+        for item in items {
+            match item.key.as_str() {
+                "id" => {
+                    let id = item
+                        .value
+                        .url_decode()
+                        .map_err(|_| ())
+                        .and_then(|value| value.parse().map_err(|_| ()))?;
+                    ids.push(id)
+                }
+                _ if strict => return Err(()),
+                _ => { /* allow extra value when not strict */ }
+            }
+        }
+
+        Ok(TaskIdsForm { ids })
+    }
+}
+
+#[derive(FromForm)]
+pub struct NewListForm {
+    pub name: String,
+}
+
+/// A form with a token `name` and one or more `scope` fields.
+pub struct NewTokenForm {
+    pub name: String,
+    pub scopes: Scopes,
+}
+
+impl<'f> FromForm<'f> for NewTokenForm {
+    type Error = ();
+
+    fn from_form(items: &mut FormItems<'f>, strict: bool) -> Result<NewTokenForm, ()> {
+        let mut name = None;
+        let mut scopes = Scopes::default();
+
+        for item in items {
+            match item.key.as_str() {
+                "name" if name.is_none() => {
+                    name = Some(item.value.url_decode().map_err(|_| ())?);
+                }
+                "scope" => {
+                    let scope = item
+                        .value
+                        .url_decode()
+                        .map_err(|_| ())
+                        .and_then(|value| value.parse().map_err(|_| ()))?;
+                    scopes.insert(scope)
+                }
+                _ if strict => return Err(()),
+                _ => { /* allow extra value when not strict */ }
+            }
+        }
+
+        Ok(NewTokenForm {
+            name: name.ok_or(())?,
+            scopes,
+        })
+    }
+}
+
+#[derive(FromForm)]
+pub struct RevokeTokenForm {
+    pub name: String,
+}
@@ -1,2 +1,9 @@
+pub mod lists;
 pub mod models;
+pub mod quick_add;
+pub mod recurrence;
+pub mod search;
 pub mod store;
+pub mod tags;
+pub mod tokens;
+pub mod users;
@@ -0,0 +1,244 @@
+//! Named task lists.
+//!
+//! There is always a default list, which is configured separately. Other lists live
+//! in subdirectories of a lists directory, one directory per list.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::{fmt, fs};
+
+use crate::models::Store;
+use crate::search::Document;
+use crate::store;
+
+pub const DEFAULT_LIST: &str = "default";
+
+pub struct Lists {
+    dir: Option<PathBuf>,
+    lists: RwLock<BTreeMap<String, Store>>,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub path: PathBuf,
+    pub source: store::Error,
+}
+
+impl Lists {
+    /// Create the set of lists from the `default` list and the lists in `dir`, if
+    /// supplied.
+    pub fn new(default: Store, dir: Option<PathBuf>) -> Result<Self, Error> {
+        let mut lists = BTreeMap::new();
+        if let Some(ref dir) = dir {
+            let with_path = |err| Error {
+                path: dir.to_owned(),
+                source: store::Error::Io(err),
+            };
+            create_dir(dir)?;
+            for entry in fs::read_dir(dir).map_err(with_path)? {
+                let entry = entry.map_err(with_path)?;
+                if !entry.file_type().map_err(with_path)?.is_dir() {
+                    continue;
+                }
+                match entry.file_name().into_string() {
+                    Ok(name) if is_valid_name(&name) && name != DEFAULT_LIST => {
+                        let store = open(&entry.path())?;
+                        lists.insert(name, store);
+                    }
+                    _ => log::warn!("ignoring {}", entry.path().display()),
+                }
+            }
+        }
+        lists.insert(DEFAULT_LIST.to_string(), default);
+
+        Ok(Lists {
+            dir,
+            lists: RwLock::new(lists),
+        })
+    }
+
+    /// Whether lists other than the default list are available.
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Store> {
+        self.lists.read().unwrap().get(name).map(Arc::clone)
+    }
+
+    /// The names of all lists, starting with the default list.
+    pub fn names(&self) -> Vec<String> {
+        let lists = self.lists.read().unwrap();
+        let mut names = vec![DEFAULT_LIST.to_string()];
+        names.extend(lists.keys().filter(|&name| name != DEFAULT_LIST).cloned());
+        names
+    }
+
+    /// Search every list for `query`, returning up to `limit` matches from each list
+    /// that has any, starting with the default list.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(String, Vec<Document>)> {
+        self.names()
+            .into_iter()
+            .filter_map(|name| {
+                let store = self.get(&name)?;
+                let store = store.lock().unwrap();
+                let documents = store
+                    .search(query)
+                    .into_iter()
+                    .take(limit)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if documents.is_empty() {
+                    None
+                } else {
+                    Some((name, documents))
+                }
+            })
+            .collect()
+    }
+
+    /// Create a new list, or return the existing list with the same name.
+    ///
+    /// `name` must be valid according to `is_valid_name`. Returns `None` if lists
+    /// are not enabled.
+    pub fn create(&self, name: &str) -> Option<Result<Store, Error>> {
+        debug_assert!(is_valid_name(name));
+        let dir = self.dir.as_ref()?;
+        let mut lists = self.lists.write().unwrap();
+        if let Some(store) = lists.get(name) {
+            return Some(Ok(Arc::clone(store)));
+        }
+
+        let store = open(&dir.join(name));
+        if let Ok(ref store) = store {
+            lists.insert(name.to_string(), Arc::clone(store));
+        }
+        Some(store)
+    }
+}
+
+/// List names are used in URLs and as directory names so they are restricted to
+/// lowercase ASCII letters, digits, `-`, and `_`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// Open the list stored in `dir`, creating it if necessary.
+#[cfg(not(feature = "sqlite"))]
+pub fn open(dir: &Path) -> Result<Store, Error> {
+    use crate::store::{AppendOnlyTaskList, DiscardedTaskList, ReadWriteTaskList};
+
+    create_dir(dir)?;
+    let with_path = |path: PathBuf| move |err| Error { path, source: err };
+    let tasks_path = dir.join("tasks.csv");
+    let tasks = ReadWriteTaskList::new(&tasks_path).map_err(with_path(tasks_path))?;
+    let completed_path = dir.join("completed.csv");
+    let completed =
+        AppendOnlyTaskList::new(&completed_path).map_err(with_path(completed_path.clone()))?;
+    let discarded_path = dir.join("discarded.csv");
+    let discarded = DiscardedTaskList::new(&discarded_path).map_err(with_path(discarded_path))?;
+
+    let store =
+        store::Store::new(tasks, completed, Some(discarded)).map_err(with_path(completed_path))?;
+    Ok(Arc::new(Mutex::new(store)))
+}
+
+/// Open the list stored in `dir`, creating it if necessary.
+#[cfg(feature = "sqlite")]
+pub fn open(dir: &Path) -> Result<Store, Error> {
+    create_dir(dir)?;
+    let path = dir.join("leaf.sqlite");
+    let (tasks, completed, discarded) = store::sqlite::open(&path).map_err(|err| Error {
+        path: path.clone(),
+        source: err,
+    })?;
+
+    let store = store::Store::new(tasks, completed, discarded).map_err(|err| Error {
+        path: path.clone(),
+        source: err,
+    })?;
+    Ok(Arc::new(Mutex::new(store)))
+}
+
+fn create_dir(dir: &Path) -> Result<(), Error> {
+    fs::create_dir_all(dir).map_err(|err| Error {
+        path: dir.to_owned(),
+        source: store::Error::Io(err),
+    })
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to open list ({})", self.path.display())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::models::NewTask;
+
+    fn default_list(dir: &Path) -> Store {
+        open(&dir.join(DEFAULT_LIST)).expect("default list")
+    }
+
+    #[test]
+    fn test_valid_name() {
+        assert!(is_valid_name("reading"));
+        assert!(is_valid_name("work-2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("Reading"));
+        assert!(!is_valid_name("../etc"));
+        assert!(!is_valid_name("a b"));
+    }
+
+    #[test]
+    fn test_lists() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let lists_dir = testdir.path().join("lists");
+
+        let lists = Lists::new(default_list(testdir.path()), Some(lists_dir.clone())).unwrap();
+        assert_eq!(lists.names(), vec![DEFAULT_LIST]);
+        let reading = lists.create("reading").unwrap().unwrap();
+        reading
+            .lock()
+            .unwrap()
+            .add(NewTask::new(String::from("a post")))
+            .unwrap();
+        assert!(lists.get("reading").is_some());
+        assert!(lists.get("missing").is_none());
+
+        // Lists are found again when reopened
+        let lists = Lists::new(default_list(testdir.path()), Some(lists_dir)).unwrap();
+        assert_eq!(lists.names(), vec![DEFAULT_LIST, "reading"]);
+        let reading = lists.get("reading").unwrap();
+        assert_eq!(reading.lock().unwrap().list()[0].description, "a post");
+
+        let results = lists.search("post", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, "reading");
+        assert!(lists.search("missing", 10).is_empty());
+    }
+
+    #[test]
+    fn test_lists_disabled() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+
+        let lists = Lists::new(default_list(testdir.path()), None).unwrap();
+        assert!(!lists.is_enabled());
+        assert!(lists.create("reading").is_none());
+        assert_eq!(lists.names(), vec![DEFAULT_LIST]);
+    }
+}
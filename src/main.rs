@@ -5,8 +5,11 @@ extern crate rocket;
 #[macro_use]
 extern crate lazy_static;
 
+mod api;
 mod auth;
+mod cli;
 mod config;
+mod feeds;
 mod form;
 mod public;
 mod tasks;
@@ -14,17 +17,20 @@ mod templates;
 
 use std::error::Error as StdError;
 use std::ffi::OsString;
+use std::path::PathBuf;
 use std::process::exit;
 use std::sync::{Arc, Mutex};
 use std::{env, fmt};
 
+use rocket::config::{ConfigError, RocketConfig};
 use rocket::Rocket;
 
 use config::Config;
-use leaf::store::{self, AppendOnlyTaskList, ReadWriteTaskList};
-
-const LEAF_TASKS_PATH: &str = "LEAF_TASKS_PATH";
-const LEAF_COMPLETED_PATH: &str = "LEAF_COMPLETED_PATH";
+use leaf::lists::{self, Lists};
+use leaf::models::Store;
+use leaf::store;
+use leaf::tokens::TokenRegistry;
+use leaf::users::Users;
 
 #[derive(Debug)]
 struct StoreError {
@@ -32,36 +38,113 @@ struct StoreError {
     source: leaf::store::Error,
 }
 
-fn rocket() -> Result<Rocket, StoreError> {
-    let tasks_path = env::var_os(LEAF_TASKS_PATH).unwrap_or_else(|| OsString::from("tasks.csv"));
-    let completed_path =
-        env::var_os(LEAF_COMPLETED_PATH).unwrap_or_else(|| OsString::from("completed.csv"));
-    let tasks = ReadWriteTaskList::new(&tasks_path).map_err(|err| StoreError {
-        path: tasks_path,
+#[cfg(not(feature = "sqlite"))]
+fn open_store(config: &Config) -> Result<Store, StoreError> {
+    use leaf::store::{AppendOnlyTaskList, DiscardedTaskList, ReadWriteTaskList};
+
+    let tasks = ReadWriteTaskList::new(&config.tasks_path).map_err(|err| StoreError {
+        path: config.tasks_path.clone().into_os_string(),
         source: err,
     })?;
-    let completed = AppendOnlyTaskList::new(&completed_path).map_err(|err| StoreError {
-        path: completed_path,
+    let completed = AppendOnlyTaskList::new(&config.completed_path).map_err(|err| StoreError {
+        path: config.completed_path.clone().into_os_string(),
         source: err,
     })?;
-    let store = store::Store::new(tasks, completed);
-    let store = Arc::new(Mutex::new(store));
+    // Discarded tasks are only recorded if a path is configured
+    let discarded = config
+        .discarded_path
+        .as_ref()
+        .map(|discarded_path| {
+            DiscardedTaskList::new(discarded_path).map_err(|err| StoreError {
+                path: discarded_path.clone().into_os_string(),
+                source: err,
+            })
+        })
+        .transpose()?;
+    let store = store::Store::new(tasks, completed, discarded).map_err(|err| StoreError {
+        path: config.completed_path.clone().into_os_string(),
+        source: err,
+    })?;
+    Ok(Arc::new(Mutex::new(store)))
+}
 
-    let config = Config::from_env().unwrap_or_else(exit_config_error);
-    let config = Arc::new(config);
+#[cfg(feature = "sqlite")]
+fn open_store(config: &Config) -> Result<Store, StoreError> {
+    let (tasks, completed, discarded) =
+        store::sqlite::open(&config.database_path).map_err(|err| StoreError {
+            path: config.database_path.clone().into_os_string(),
+            source: err,
+        })?;
+    let store = store::Store::new(tasks, completed, discarded).map_err(|err| StoreError {
+        path: config.database_path.clone().into_os_string(),
+        source: err,
+    })?;
+    Ok(Arc::new(Mutex::new(store)))
+}
 
-    let server = rocket::ignite()
+fn rocket(config: Config) -> Result<Rocket, Box<dyn StdError>> {
+    let users = match (&config.users_path, &config.password_hash) {
+        (Some(users_path), _) => Users::open(users_path)?,
+        (None, Some(password_hash)) => {
+            let store = open_store(&config)?;
+            // Additional named lists are only available if a directory to hold them is configured
+            let lists = Lists::new(store, config.lists_path.clone()).map_err(StoreError::from)?;
+            let tokens = TokenRegistry::open(&config.tokens_path).map_err(|err| StoreError {
+                path: config.tokens_path.clone().into_os_string(),
+                source: err,
+            })?;
+            Users::single(password_hash.clone(), lists, tokens)
+        }
+        (None, None) => unreachable!("config requires a password hash or users path"),
+    };
+
+    let rocket_config = rocket_config(config.secret_key.as_deref())?;
+    let server = rocket::custom(rocket_config)
         .mount("/", auth::routes())
         .mount("/", tasks::routes())
         .mount("/", public::routes())
-        .manage(config)
-        .manage(store);
+        .mount("/", feeds::routes())
+        .mount(api::BASE, api::routes())
+        .manage(Arc::new(config))
+        .manage(users);
 
     Ok(server)
 }
 
+/// Read Rocket's settings from Rocket.toml and `ROCKET_*` environment variables,
+/// as `rocket::ignite` does, and set the secret key from the Leaf config.
+fn rocket_config(secret_key: Option<&str>) -> Result<rocket::Config, ConfigError> {
+    let config = match RocketConfig::read() {
+        Ok(config) => config,
+        Err(ConfigError::NotFound) => RocketConfig::active_default()?,
+        Err(err) => return Err(err),
+    };
+    let mut config = config.active().clone();
+    // Leaf reads ROCKET_SECRET_KEY_FILE itself
+    config.extras.remove("secret_key_file");
+    if let Some(secret_key) = secret_key {
+        config.set_secret_key(secret_key)?;
+    }
+    Ok(config)
+}
+
 fn main() {
-    let rocket = match rocket() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let args = cli::parse(&args).unwrap_or_else(|err| {
+        eprintln!("Error: {}\n\n{}", err, cli::USAGE);
+        exit(2);
+    });
+    let config_path = args
+        .config
+        .or_else(|| env::var_os(config::LEAF_CONFIG).map(PathBuf::from));
+
+    if let Some(command) = args.command {
+        exit(run_command(command, config_path, args.user));
+    }
+
+    let config = Config::load(config_path.as_deref()).unwrap_or_else(exit_config_error);
+    config.check_server().unwrap_or_else(exit_config_error);
+    let rocket = match rocket(config) {
         Ok(rocket) => rocket,
         Err(err) => {
             eprintln!("{}", err);
@@ -74,7 +157,38 @@ fn main() {
     rocket.launch();
 }
 
-fn exit_config_error(err: String) -> Config {
+fn run_command(command: cli::Command, config_path: Option<PathBuf>, user: Option<String>) -> i32 {
+    let result = cli::run(command, || {
+        let config = Config::load(config_path.as_deref()).unwrap_or_else(exit_config_error);
+        open_lists(config, user.as_deref())
+    });
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            if let Some(source) = err.source() {
+                eprintln!(" - Caused by: {}", source);
+            }
+            1
+        }
+    }
+}
+
+/// The lists the commands work with. With a users directory these are the lists of
+/// `user`, or the first user, otherwise the configured lists.
+fn open_lists(config: Config, user: Option<&str>) -> Result<Lists, Box<dyn StdError>> {
+    match (&config.users_path, user) {
+        (Some(users_path), user) => Ok(Users::open_lists(users_path, user)?),
+        (None, Some(_)) => Err(format!("--user requires {}", config::LEAF_USERS_PATH).into()),
+        (None, None) => {
+            let store = open_store(&config)?;
+            let lists = Lists::new(store, config.lists_path).map_err(StoreError::from)?;
+            Ok(lists)
+        }
+    }
+}
+
+fn exit_config_error<T>(err: String) -> T {
     eprintln!(
         "Configuration error:\n\n{}\n\nSee https://github.com/wezm/leaf-tasks#configuration",
         err
@@ -92,6 +206,15 @@ impl fmt::Display for StoreError {
     }
 }
 
+impl From<lists::Error> for StoreError {
+    fn from(err: lists::Error) -> Self {
+        StoreError {
+            path: err.path.into_os_string(),
+            source: err.source,
+        }
+    }
+}
+
 impl StdError for StoreError {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         Some(&self.source)
@@ -1,12 +1,28 @@
 use chrono::prelude::*;
 use rusty_ulid::Ulid;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
-use crate::store::{self, AppendOnlyTaskList, ReadWriteTaskList};
+use crate::recurrence::Recurrence;
+use crate::{store, tags};
 
 // TODO: Move
-pub type Store = Arc<Mutex<store::Store<ReadWriteTaskList, AppendOnlyTaskList>>>;
+#[cfg(not(feature = "sqlite"))]
+type Backend = store::Store<
+    store::ReadWriteTaskList,
+    store::AppendOnlyTaskList,
+    Option<store::DiscardedTaskList>,
+>;
+#[cfg(feature = "sqlite")]
+type Backend = store::Store<
+    store::sqlite::TaskList,
+    store::sqlite::CompletedTaskList,
+    store::sqlite::DiscardedTaskList,
+>;
+pub type Store = Arc<Mutex<Backend>>;
 
 pub type TaskId = Ulid;
 pub type Timestamp = DateTime<Utc>;
@@ -15,19 +31,123 @@ pub type Timestamp = DateTime<Utc>;
 #[derive(Debug, Deserialize)]
 pub struct NewTask {
     pub description: String,
+    #[serde(default)]
+    pub due: Option<NaiveDate>,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    /// The task this is a subtask of.
+    #[serde(default)]
+    pub parent: Option<TaskId>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(from = "TaskRecord")]
 pub struct Task {
     pub id: TaskId,
     pub description: String,
+    pub created_at: Timestamp,
+    pub due: Option<NaiveDate>,
+    pub priority: Priority,
+    pub notes: Option<String>,
+    pub recurrence: Option<Recurrence>,
+    /// The task is snoozed, and not shown in the list, before this date.
+    pub hidden_until: Option<NaiveDate>,
+    /// The task this is a subtask of. Subtasks are only one level deep.
+    pub parent: Option<TaskId>,
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct CompletedTask<'task> {
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(from = "CompletedTaskRecord")]
+pub struct CompletedTask {
     pub id: TaskId,
-    pub description: &'task str,
+    pub description: String,
     pub completed_at: Timestamp,
+    pub created_at: Timestamp,
+    pub due: Option<NaiveDate>,
+    pub priority: Priority,
+    pub notes: Option<String>,
+    pub recurrence: Option<Recurrence>,
+    pub parent: Option<TaskId>,
+    /// The occurrence that was added when this recurring task was completed.
+    pub next_id: Option<TaskId>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    None,
+    Low,
+    Normal,
+    High,
+}
+
+#[derive(Debug)]
+pub struct ParsePriorityError(String);
+
+/// A `Task` as read from storage. Tasks stored before creation times were recorded
+/// don't have one, in which case it is taken from the id.
+#[derive(Deserialize)]
+struct TaskRecord {
+    id: TaskId,
+    description: String,
+    #[serde(default)]
+    created_at: Option<Timestamp>,
+    #[serde(default)]
+    due: Option<NaiveDate>,
+    #[serde(default)]
+    priority: Priority,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    recurrence: Option<Recurrence>,
+    #[serde(default)]
+    hidden_until: Option<NaiveDate>,
+    #[serde(default)]
+    parent: Option<TaskId>,
+}
+
+/// A `CompletedTask` as read from storage. See `TaskRecord`.
+#[derive(Deserialize)]
+struct CompletedTaskRecord {
+    id: TaskId,
+    description: String,
+    completed_at: Timestamp,
+    #[serde(default)]
+    created_at: Option<Timestamp>,
+    #[serde(default)]
+    due: Option<NaiveDate>,
+    #[serde(default)]
+    priority: Priority,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    recurrence: Option<Recurrence>,
+    #[serde(default)]
+    parent: Option<TaskId>,
+    #[serde(default)]
+    next_id: Option<TaskId>,
+}
+
+/// Where to move a task to within its list.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Position {
+    Top,
+    Bottom,
+    Before(TaskId),
+    After(TaskId),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DiscardedTask {
+    pub id: TaskId,
+    pub description: String,
+    pub discarded_at: Timestamp,
+    pub reason: Option<String>,
 }
 
 // Ideally we would use something like this for the form but serde_urlencoded
@@ -39,3 +159,144 @@ pub struct CompletedTask<'task> {
 //    pub description: String,
 //    pub completed: Vec<TaskId>
 //}
+
+impl Task {
+    /// Whether the task is due before `today`.
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        self.due.map_or(false, |due| due < today)
+    }
+
+    pub fn is_due_today(&self, today: NaiveDate) -> bool {
+        self.due == Some(today)
+    }
+
+    /// Whether the task is snoozed until after `today`.
+    pub fn is_hidden(&self, today: NaiveDate) -> bool {
+        self.hidden_until.map_or(false, |until| until > today)
+    }
+
+    /// The `#tags` in the description.
+    pub fn tags(&self) -> BTreeSet<String> {
+        tags::parse(&self.description)
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags().contains(&tag.to_lowercase())
+    }
+
+    /// The task to add when this one is completed on `today`, if it recurs and the
+    /// next date can be represented.
+    pub fn next_occurrence(&self, today: NaiveDate) -> Option<NewTask> {
+        let due = self.recurrence?.next_after(self.due, today)?;
+        Some(NewTask {
+            description: self.description.clone(),
+            due: Some(due),
+            priority: self.priority,
+            notes: self.notes.clone(),
+            recurrence: self.recurrence,
+            parent: self.parent,
+        })
+    }
+}
+
+impl Priority {
+    pub const ALL: [Priority; 4] = [
+        Priority::None,
+        Priority::Low,
+        Priority::Normal,
+        Priority::High,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::None => "none",
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::None
+    }
+}
+
+impl FromStr for Priority {
+    type Err = ParsePriorityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Priority::ALL
+            .iter()
+            .copied()
+            .find(|priority| priority.as_str() == s)
+            .ok_or_else(|| ParsePriorityError(s.to_string()))
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for ParsePriorityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown priority: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParsePriorityError {}
+
+/// Notes as entered in a form or the API, with line endings normalised and leading
+/// blank lines and trailing whitespace removed. Blank notes are `None`.
+pub fn clean_notes(notes: &str) -> Option<String> {
+    let notes = notes.replace("\r\n", "\n");
+    let notes = notes.trim_end().trim_start_matches('\n');
+    if notes.trim().is_empty() {
+        None
+    } else {
+        Some(notes.to_string())
+    }
+}
+
+/// The time the task with `id` was created, according to the timestamp in the id.
+pub fn created_at_from_id(id: TaskId) -> Timestamp {
+    id.datetime().trunc_subsecs(0)
+}
+
+impl From<TaskRecord> for Task {
+    fn from(record: TaskRecord) -> Self {
+        let id = record.id;
+        Task {
+            id,
+            description: record.description,
+            created_at: record.created_at.unwrap_or_else(|| created_at_from_id(id)),
+            due: record.due,
+            priority: record.priority,
+            notes: record.notes,
+            recurrence: record.recurrence,
+            hidden_until: record.hidden_until,
+            parent: record.parent,
+        }
+    }
+}
+
+impl From<CompletedTaskRecord> for CompletedTask {
+    fn from(record: CompletedTaskRecord) -> Self {
+        let id = record.id;
+        CompletedTask {
+            id,
+            description: record.description,
+            completed_at: record.completed_at,
+            created_at: record.created_at.unwrap_or_else(|| created_at_from_id(id)),
+            due: record.due,
+            priority: record.priority,
+            notes: record.notes,
+            recurrence: record.recurrence,
+            parent: record.parent,
+            next_id: record.next_id,
+        }
+    }
+}
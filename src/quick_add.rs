@@ -0,0 +1,193 @@
+//! Quick-add syntax.
+//!
+//! The description of a new task can include tokens that set other fields:
+//!
+//! * `@today`, `@tomorrow`, `@monday`…`@sunday`, or `@2026-11-01` sets the due date.
+//! * `!low`, `!normal`, or `!high` sets the priority.
+//! * `^list-name` adds the task to another list.
+//!
+//! Recognised tokens are removed from the description. Anything else, such as an
+//! unknown `@word`, is left as is. `#tags` are also left in place, as tags are read
+//! from the description.
+//!
+//! Fields given explicitly take precedence over the description: a date chosen in
+//! the form, and the `due`, `priority`, and `list` of an API request, even when
+//! `due` is `null` or `priority` is `none`. The list of the page a task is added
+//! from isn't chosen explicitly, so `^list-name` still adds the task elsewhere.
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+use crate::lists;
+use crate::models::{NewTask, Priority};
+
+#[derive(Debug, PartialEq)]
+pub struct QuickAdd {
+    pub description: String,
+    pub due: Option<NaiveDate>,
+    pub priority: Priority,
+    pub list: Option<String>,
+}
+
+/// Parse `input`, interpreting relative dates relative to `today`. When a token
+/// appears more than once the last one wins.
+pub fn parse(input: &str, today: NaiveDate) -> QuickAdd {
+    let mut due = None;
+    let mut priority = None;
+    let mut list = None;
+    let mut words = Vec::new();
+
+    for word in input.split_whitespace() {
+        if let Some(date) = word
+            .strip_prefix('@')
+            .and_then(|date| parse_date(date, today))
+        {
+            due = Some(date);
+        } else if let Some(value) = word.strip_prefix('!').and_then(parse_priority) {
+            priority = Some(value);
+        } else if let Some(name) = word
+            .strip_prefix('^')
+            .filter(|name| lists::is_valid_name(name))
+        {
+            list = Some(name.to_string());
+        } else {
+            words.push(word);
+        }
+    }
+
+    // Only rebuild the description when something was removed so that its
+    // whitespace is otherwise preserved
+    let description = if due.is_none() && priority.is_none() && list.is_none() {
+        input.trim().to_string()
+    } else {
+        words.join(" ")
+    };
+
+    QuickAdd {
+        description,
+        due,
+        priority: priority.unwrap_or_default(),
+        list,
+    }
+}
+
+impl From<QuickAdd> for NewTask {
+    fn from(quick_add: QuickAdd) -> Self {
+        NewTask {
+            description: quick_add.description,
+            due: quick_add.due,
+            priority: quick_add.priority,
+            notes: None,
+            recurrence: None,
+            parent: None,
+        }
+    }
+}
+
+fn parse_date(value: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match value.to_lowercase().as_str() {
+        "today" => Some(today),
+        "tomorrow" => Some(today + Duration::days(1)),
+        value => match parse_weekday(value) {
+            Some(weekday) => Some(next_weekday(today, weekday)),
+            None => NaiveDate::parse_from_str(value, "%Y-%m-%d").ok(),
+        },
+    }
+}
+
+fn parse_priority(value: &str) -> Option<Priority> {
+    match value.to_lowercase().parse() {
+        // `!none` isn't useful when adding a task
+        Ok(Priority::None) | Err(_) => None,
+        Ok(priority) => Some(priority),
+    }
+}
+
+fn parse_weekday(value: &str) -> Option<Weekday> {
+    let weekday = match value {
+        "mon" | "monday" => Weekday::Mon,
+        "tue" | "tuesday" => Weekday::Tue,
+        "wed" | "wednesday" => Weekday::Wed,
+        "thu" | "thursday" => Weekday::Thu,
+        "fri" | "friday" => Weekday::Fri,
+        "sat" | "saturday" => Weekday::Sat,
+        "sun" | "sunday" => Weekday::Sun,
+        _ => return None,
+    };
+    Some(weekday)
+}
+
+/// The next `weekday` after `today`. If today is that day it's a week away.
+fn next_weekday(today: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days = (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+    today + Duration::days(if days == 0 { 7 } else { i64::from(days) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A Sunday
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd(2026, 10, 18)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("  Read  a post #reading ", today()),
+            QuickAdd {
+                description: String::from("Read  a post #reading"),
+                due: None,
+                priority: Priority::None,
+                list: None,
+            }
+        );
+        assert_eq!(
+            parse("Buy milk @tomorrow !High ^errands #shopping", today()),
+            QuickAdd {
+                description: String::from("Buy milk #shopping"),
+                due: Some(NaiveDate::from_ymd(2026, 10, 19)),
+                priority: Priority::High,
+                list: Some(String::from("errands")),
+            }
+        );
+        assert_eq!(
+            parse("@2026-11-01 Pay rent @Today", today()).due,
+            Some(today())
+        );
+    }
+
+    #[test]
+    fn test_parse_unrecognised() {
+        let parsed = parse("Email bob@example.com @someone !none ^Not/A/List!", today());
+        assert_eq!(
+            parsed.description,
+            "Email bob@example.com @someone !none ^Not/A/List!"
+        );
+        assert_eq!(parsed.due, None);
+        assert_eq!(parsed.priority, Priority::None);
+        assert_eq!(parsed.list, None);
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(
+            parse_date("2026-11-01", today()),
+            Some(NaiveDate::from_ymd(2026, 11, 1))
+        );
+        assert_eq!(
+            parse_date("mon", today()),
+            Some(NaiveDate::from_ymd(2026, 10, 19))
+        );
+        assert_eq!(
+            parse_date("Saturday", today()),
+            Some(NaiveDate::from_ymd(2026, 10, 24))
+        );
+        assert_eq!(
+            parse_date("sunday", today()),
+            Some(NaiveDate::from_ymd(2026, 10, 25))
+        );
+        assert_eq!(parse_date("2026-13-01", today()), None);
+        assert_eq!(parse_date("someday", today()), None);
+    }
+}
@@ -0,0 +1,332 @@
+//! Recurring tasks.
+//!
+//! A recurrence rule can be written as `daily`, `weekly`, `monthly`, `yearly`,
+//! `every N days` (or weeks, months, years), or as an iCalendar RRULE limited to the
+//! `FREQ` and `INTERVAL` parts, e.g. `RRULE:FREQ=WEEKLY;INTERVAL=2`. Rules are
+//! stored in the canonical form produced by `Display`. Intervals are limited to
+//! `MAX_INTERVAL`.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The largest interval a rule can have, e.g. `every 1000 years`.
+pub const MAX_INTERVAL: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recurrence {
+    frequency: Frequency,
+    interval: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseRecurrenceError(String);
+
+impl Recurrence {
+    pub fn new(frequency: Frequency, interval: u32) -> Self {
+        Recurrence {
+            frequency,
+            interval: interval.max(1).min(MAX_INTERVAL),
+        }
+    }
+
+    /// The date one interval after `date`, or `None` if it is past the latest date
+    /// that can be represented. Monthly and yearly rules that land on a day the
+    /// month doesn't have use the last day of the month instead.
+    pub fn next(&self, date: NaiveDate) -> Option<NaiveDate> {
+        self.advance(date, 1)
+    }
+
+    /// The first date after `today` that follows on from `due`, or a single interval
+    /// after `today` if there is no due date. `None` if there is no such date that
+    /// can be represented.
+    pub fn next_after(&self, due: Option<NaiveDate>, today: NaiveDate) -> Option<NaiveDate> {
+        let due = match due {
+            Some(due) if due < today => due,
+            due => return self.next(due.unwrap_or(today)),
+        };
+        // Work out how many intervals have passed rather than stepping through them,
+        // as that could take a long time for a task due long ago. For monthly and
+        // yearly rules this can be one short, so step at most twice from there.
+        let interval = i64::from(self.interval);
+        let passed = match self.frequency {
+            Frequency::Daily => (today - due).num_days() / interval,
+            Frequency::Weekly => (today - due).num_weeks() / interval,
+            Frequency::Monthly => months_between(due, today) / interval,
+            Frequency::Yearly => months_between(due, today) / (interval * 12),
+        };
+        let mut count = passed.max(1);
+        loop {
+            let next = self.advance(due, count)?;
+            if next > today {
+                return Some(next);
+            }
+            count += 1;
+        }
+    }
+
+    /// The date `count` intervals after `date`, see `next`.
+    fn advance(&self, date: NaiveDate, count: i64) -> Option<NaiveDate> {
+        let intervals = count.checked_mul(i64::from(self.interval))?;
+        match self.frequency {
+            Frequency::Daily => date.checked_add_signed(Duration::days(intervals)),
+            Frequency::Weekly => date.checked_add_signed(Duration::weeks(intervals)),
+            Frequency::Monthly => add_months(date, u32::try_from(intervals).ok()?),
+            Frequency::Yearly => add_months(date, u32::try_from(intervals.checked_mul(12)?).ok()?),
+        }
+    }
+}
+
+/// The number of calendar months from the month of `from` to the month of `to`.
+fn months_between(from: NaiveDate, to: NaiveDate) -> i64 {
+    i64::from(to.year() - from.year()) * 12 + i64::from(to.month0()) - i64::from(from.month0())
+}
+
+fn add_months(date: NaiveDate, months: u32) -> Option<NaiveDate> {
+    let month0 = date.month0().checked_add(months)?;
+    let year = date.year().checked_add(i32::try_from(month0 / 12).ok()?)?;
+    let month = month0 % 12 + 1;
+    (1..=date.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+impl Frequency {
+    fn unit(self) -> &'static str {
+        match self {
+            Frequency::Daily => "day",
+            Frequency::Weekly => "week",
+            Frequency::Monthly => "month",
+            Frequency::Yearly => "year",
+        }
+    }
+
+    fn from_unit(unit: &str) -> Option<Self> {
+        match unit.strip_suffix('s').unwrap_or(unit) {
+            "day" => Some(Frequency::Daily),
+            "week" => Some(Frequency::Weekly),
+            "month" => Some(Frequency::Monthly),
+            "year" => Some(Frequency::Yearly),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = ParseRecurrenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rule = s.trim().to_lowercase();
+        let error = || ParseRecurrenceError(s.trim().to_string());
+        let recurrence = match rule.as_str() {
+            "daily" => Recurrence::new(Frequency::Daily, 1),
+            "weekly" => Recurrence::new(Frequency::Weekly, 1),
+            "monthly" => Recurrence::new(Frequency::Monthly, 1),
+            "yearly" => Recurrence::new(Frequency::Yearly, 1),
+            rule if rule.starts_with("every ") => {
+                let words = rule.split_whitespace().skip(1).collect::<Vec<_>>();
+                let (interval, unit) = match words[..] {
+                    [unit] => (1, unit),
+                    [interval, unit] => (interval.parse().map_err(|_| error())?, unit),
+                    _ => return Err(error()),
+                };
+                let frequency = Frequency::from_unit(unit).ok_or_else(error)?;
+                if !(1..=MAX_INTERVAL).contains(&interval) {
+                    return Err(error());
+                }
+                Recurrence::new(frequency, interval)
+            }
+            rule => parse_rrule(rule.strip_prefix("rrule:").unwrap_or(rule)).ok_or_else(error)?,
+        };
+        Ok(recurrence)
+    }
+}
+
+/// Parse the `FREQ` and `INTERVAL` parts of a lowercased RRULE. Rules with other
+/// parts are not supported.
+fn parse_rrule(rule: &str) -> Option<Recurrence> {
+    let mut frequency = None;
+    let mut interval = 1;
+    for part in rule.split(';').filter(|part| !part.is_empty()) {
+        let mut key_value = part.splitn(2, '=');
+        match (key_value.next()?, key_value.next()?) {
+            ("freq", "daily") => frequency = Some(Frequency::Daily),
+            ("freq", "weekly") => frequency = Some(Frequency::Weekly),
+            ("freq", "monthly") => frequency = Some(Frequency::Monthly),
+            ("freq", "yearly") => frequency = Some(Frequency::Yearly),
+            ("interval", value) => {
+                interval = value
+                    .parse()
+                    .ok()
+                    .filter(|n| (1..=MAX_INTERVAL).contains(n))?
+            }
+            _ => return None,
+        }
+    }
+    frequency.map(|frequency| Recurrence::new(frequency, interval))
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.interval, self.frequency) {
+            (1, Frequency::Daily) => f.write_str("daily"),
+            (1, Frequency::Weekly) => f.write_str("weekly"),
+            (1, Frequency::Monthly) => f.write_str("monthly"),
+            (1, Frequency::Yearly) => f.write_str("yearly"),
+            (interval, frequency) => write!(f, "every {} {}s", interval, frequency.unit()),
+        }
+    }
+}
+
+impl fmt::Display for ParseRecurrenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported recurrence rule: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseRecurrenceError {}
+
+impl Serialize for Recurrence {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Recurrence {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rule = String::deserialize(deserializer)?;
+        rule.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(rule: &str) -> Result<Recurrence, ParseRecurrenceError> {
+        rule.parse()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("Weekly"), Ok(Recurrence::new(Frequency::Weekly, 1)));
+        assert_eq!(
+            parse("every 3 days"),
+            Ok(Recurrence::new(Frequency::Daily, 3))
+        );
+        assert_eq!(
+            parse("every month"),
+            Ok(Recurrence::new(Frequency::Monthly, 1))
+        );
+        assert_eq!(
+            parse("RRULE:FREQ=WEEKLY;INTERVAL=2"),
+            Ok(Recurrence::new(Frequency::Weekly, 2))
+        );
+        assert_eq!(
+            parse("FREQ=YEARLY"),
+            Ok(Recurrence::new(Frequency::Yearly, 1))
+        );
+
+        assert!(parse("").is_err());
+        assert!(parse("every 0 days").is_err());
+        assert!(parse("every 1000 years").is_ok());
+        assert!(parse("every 300000 years").is_err());
+        assert!(parse("FREQ=YEARLY;INTERVAL=300000").is_err());
+        assert!(parse("every 99999999999 days").is_err());
+        assert!(parse("every other day").is_err());
+        assert!(parse("FREQ=WEEKLY;BYDAY=MO").is_err());
+        assert!(parse("INTERVAL=2").is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        for rule in &["daily", "weekly", "monthly", "yearly", "every 2 weeks"] {
+            assert_eq!(parse(rule).unwrap().to_string(), *rule);
+        }
+        assert_eq!(
+            parse("FREQ=DAILY;INTERVAL=10").unwrap().to_string(),
+            "every 10 days"
+        );
+    }
+
+    #[test]
+    fn test_next() {
+        let date = |y, m, d| NaiveDate::from_ymd(y, m, d);
+        let monthly = Recurrence::new(Frequency::Monthly, 1);
+        assert_eq!(monthly.next(date(2026, 1, 31)), Some(date(2026, 2, 28)));
+        assert_eq!(monthly.next(date(2026, 12, 15)), Some(date(2027, 1, 15)));
+        assert_eq!(
+            Recurrence::new(Frequency::Yearly, 1).next(date(2028, 2, 29)),
+            Some(date(2029, 2, 28))
+        );
+        assert_eq!(
+            Recurrence::new(Frequency::Daily, 3).next(date(2026, 10, 30)),
+            Some(date(2026, 11, 2))
+        );
+
+        let weekly = Recurrence::new(Frequency::Weekly, 1);
+        let today = date(2026, 10, 18);
+        assert_eq!(weekly.next_after(None, today), Some(date(2026, 10, 25)));
+        assert_eq!(
+            weekly.next_after(Some(date(2026, 10, 20)), today),
+            Some(date(2026, 10, 27))
+        );
+        // Overdue tasks skip the occurrences that have already passed
+        assert_eq!(
+            weekly.next_after(Some(date(2026, 10, 1)), today),
+            Some(date(2026, 10, 22))
+        );
+        assert_eq!(
+            weekly.next_after(Some(date(2026, 10, 11)), today),
+            Some(date(2026, 10, 25))
+        );
+        assert_eq!(
+            Recurrence::new(Frequency::Daily, 3).next_after(Some(date(2026, 10, 15)), today),
+            Some(date(2026, 10, 21))
+        );
+        assert_eq!(
+            monthly.next_after(Some(date(2026, 7, 31)), today),
+            Some(date(2026, 10, 31))
+        );
+        assert_eq!(
+            monthly.next_after(Some(date(2026, 7, 10)), today),
+            Some(date(2026, 11, 10))
+        );
+        assert_eq!(
+            Recurrence::new(Frequency::Yearly, 2).next_after(Some(date(2024, 11, 1)), today),
+            Some(date(2026, 11, 1))
+        );
+        // Without stepping through every interval since a date long ago
+        assert_eq!(
+            Recurrence::new(Frequency::Daily, 1).next_after(Some(date(-200_000, 1, 1)), today),
+            Some(date(2026, 10, 19))
+        );
+        assert_eq!(
+            monthly.next_after(Some(date(-200_000, 1, 18)), today),
+            Some(date(2026, 11, 18))
+        );
+
+        // Dates past the latest that can be represented end the rule
+        let last = date(262_000, 1, 1);
+        assert_eq!(Recurrence::new(Frequency::Yearly, 1000).next(last), None);
+        assert_eq!(
+            Recurrence::new(Frequency::Daily, 1000).next(chrono::naive::MAX_DATE),
+            None
+        );
+        assert_eq!(
+            Recurrence::new(Frequency::Yearly, 1000).next_after(Some(last), today),
+            None
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Full-text search.
+//!
+//! Each list keeps an in-memory index of its active and completed tasks, built when
+//! the list is opened and updated as tasks change. The description and notes of a
+//! task are split into lowercase words on anything that isn't a letter or digit, so
+//! URLs can be found by their parts, e.g. `example` or `async` in
+//! `https://example.com/async-traits`.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::Serialize;
+
+use crate::models::{CompletedTask, Task, TaskId, Timestamp};
+
+#[derive(Default)]
+pub struct Index {
+    documents: HashMap<TaskId, Document>,
+    words: BTreeMap<String, BTreeSet<TaskId>>,
+}
+
+/// A task as stored in the index.
+#[derive(Debug, Clone, Serialize)]
+pub struct Document {
+    pub id: TaskId,
+    pub description: String,
+    pub notes: Option<String>,
+    /// When the task was completed, or `None` if it is active.
+    pub completed_at: Option<Timestamp>,
+}
+
+impl Index {
+    pub fn new() -> Self {
+        Index::default()
+    }
+
+    /// Add `document` to the index, replacing any existing document with the same id.
+    pub fn insert(&mut self, document: Document) {
+        self.remove(document.id);
+        for word in document_words(&document) {
+            self.words.entry(word).or_default().insert(document.id);
+        }
+        self.documents.insert(document.id, document);
+    }
+
+    pub fn remove(&mut self, id: TaskId) {
+        let document = match self.documents.remove(&id) {
+            Some(document) => document,
+            None => return,
+        };
+        for word in document_words(&document) {
+            if let Some(ids) = self.words.get_mut(&word) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.words.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// The documents containing every word in `query`, or a word starting with it.
+    /// Active tasks come first, then completed tasks, most recent first.
+    pub fn search(&self, query: &str) -> Vec<&Document> {
+        let mut matches: Option<BTreeSet<TaskId>> = None;
+        for word in words(query) {
+            let ids = self
+                .words
+                .range(word.clone()..)
+                .take_while(|(indexed, _)| indexed.starts_with(&word))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect::<BTreeSet<_>>();
+            matches = Some(match matches {
+                Some(matches) => matches.intersection(&ids).copied().collect(),
+                None => ids,
+            });
+        }
+
+        let mut documents = matches
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|id| self.documents.get(id))
+            .collect::<Vec<_>>();
+        documents.sort_by_key(|document| {
+            (
+                document.completed_at.is_some(),
+                Reverse(document.completed_at),
+                Reverse(document.id),
+            )
+        });
+        documents
+    }
+}
+
+/// The distinct words in the description and notes of `document`.
+fn document_words(document: &Document) -> BTreeSet<String> {
+    let notes = document.notes.as_deref().unwrap_or("");
+    words(&document.description).chain(words(notes)).collect()
+}
+
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+impl From<&Task> for Document {
+    fn from(task: &Task) -> Self {
+        Document {
+            id: task.id,
+            description: task.description.clone(),
+            notes: task.notes.clone(),
+            completed_at: None,
+        }
+    }
+}
+
+impl From<&CompletedTask> for Document {
+    fn from(task: &CompletedTask) -> Self {
+        Document {
+            id: task.id,
+            description: task.description.clone(),
+            notes: task.notes.clone(),
+            completed_at: Some(task.completed_at),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+    use rusty_ulid::Ulid;
+
+    fn document(description: &str, notes: Option<&str>, completed: Option<u32>) -> Document {
+        Document {
+            id: Ulid::generate(),
+            description: description.to_string(),
+            notes: notes.map(String::from),
+            completed_at: completed.map(|day| Utc.ymd(2026, 3, day).and_hms(12, 0, 0)),
+        }
+    }
+
+    fn ids(documents: Vec<&Document>) -> Vec<TaskId> {
+        documents.iter().map(|document| document.id).collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let mut index = Index::new();
+        let post = document(
+            "Read https://example.com/async-traits",
+            Some("Saved for the Rust newsletter"),
+            Some(1),
+        );
+        let newer = document("Async closures #rust", None, Some(20));
+        let active = document("Try async in the CLI", None, None);
+        for document in &[&post, &newer, &active] {
+            index.insert((*document).clone());
+        }
+
+        assert_eq!(
+            ids(index.search("async")),
+            vec![active.id, newer.id, post.id]
+        );
+        assert_eq!(ids(index.search("Async TRAITS")), vec![post.id]);
+        assert_eq!(ids(index.search("example.com")), vec![post.id]);
+        assert_eq!(ids(index.search("newslet")), vec![post.id]);
+        assert_eq!(ids(index.search("rust")), vec![newer.id, post.id]);
+        assert!(index.search("async python").is_empty());
+        assert!(index.search("").is_empty());
+        assert!(index.search(" ?! ").is_empty());
+    }
+
+    #[test]
+    fn test_update() {
+        let mut index = Index::new();
+        let mut task = document("Write the release notes", None, None);
+        index.insert(task.clone());
+
+        task.description = String::from("Publish the release");
+        task.completed_at = Some(Utc::now());
+        index.insert(task.clone());
+        assert!(index.search("notes").is_empty());
+        assert_eq!(index.search("publish")[0].completed_at, task.completed_at);
+
+        index.remove(task.id);
+        assert!(index.search("release").is_empty());
+        assert!(index.words.is_empty());
+    }
+}
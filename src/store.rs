@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::fs::{File, OpenOptions};
 use std::io::BufReader;
@@ -6,8 +7,16 @@ use std::{fmt, fs, io};
 
 use chrono::prelude::*;
 use rusty_ulid::Ulid;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 
-use crate::models::{CompletedTask, NewTask, Task, TaskId};
+use crate::models::{
+    self, CompletedTask, DiscardedTask, NewTask, Position, Priority, Task, TaskId,
+};
+use crate::search::{self, Document};
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 
 // This module operates under the assumption that the active task list will generally remain
 // fairly small, but the completed list will be more or less ever growing. This, we typically
@@ -18,6 +27,8 @@ use crate::models::{CompletedTask, NewTask, Task, TaskId};
 pub enum Error {
     Io(io::Error),
     Csv(csv::Error),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
 }
 
 pub trait CreateTask {
@@ -25,28 +36,60 @@ pub trait CreateTask {
 }
 
 pub trait AddTasks {
-    fn add(&mut self, tasks: &[&Task]) -> Result<(), Error>;
+    fn add(&mut self, tasks: &[CompletedTask]) -> Result<(), Error>;
+}
+
+pub trait AddDiscardedTasks {
+    fn add_discarded(&mut self, tasks: &[&Task], reason: Option<&str>) -> Result<(), Error>;
 }
 
 pub trait ListTasks {
     fn list(&self) -> &[Task];
 }
 
+pub trait ListCompletedTasks {
+    /// Read all completed tasks, in the order they were completed.
+    fn list_completed(&self) -> Result<Vec<CompletedTask>, Error>;
+}
+
+pub trait RemoveCompletedTasks {
+    fn remove_completed(
+        &mut self,
+        task_ids: &[TaskId],
+        body: impl FnMut(Vec<CompletedTask>) -> Result<(), Error>,
+    ) -> Result<(), Error>;
+}
+
+pub trait EditTask {
+    /// Apply `edit` to the task with id `task_id`, if present.
+    fn edit(&mut self, task_id: TaskId, edit: impl FnOnce(&mut Task)) -> Result<(), Error>;
+}
+
+pub trait MoveTask {
+    /// Move the task with id `task_id` to `position`, if both are present.
+    fn move_task(&mut self, task_id: TaskId, position: Position) -> Result<(), Error>;
+}
+
 pub trait RemoveTasks {
+    /// Remove the tasks with ids in `task_ids`, passing them to `body`, which
+    /// returns tasks to add to the end of the list. Nothing changes if `body` fails.
     fn remove(
         &mut self,
         task_ids: &[TaskId],
-        body: impl FnMut(Vec<&Task>) -> Result<(), Error>,
+        body: impl FnOnce(Vec<&Task>) -> Result<Vec<Task>, Error>,
     ) -> Result<(), Error>;
 }
 
-pub struct Store<Tasks, Completed>
+pub struct Store<Tasks, Completed, Discarded>
 where
-    Tasks: CreateTask + RemoveTasks + ListTasks,
-    Completed: AddTasks,
+    Tasks: CreateTask + EditTask + MoveTask + RemoveTasks + ListTasks,
+    Completed: AddTasks + ListCompletedTasks + RemoveCompletedTasks,
+    Discarded: AddDiscardedTasks,
 {
     tasks: Tasks,
     completed: Completed,
+    discarded: Discarded,
+    index: search::Index,
 }
 
 pub struct ReadWriteTaskList {
@@ -56,67 +99,393 @@ pub struct ReadWriteTaskList {
 
 pub struct AppendOnlyTaskList {
     writer: csv::Writer<File>,
+    path: PathBuf,
 }
 
-impl<Tasks, Completed> Store<Tasks, Completed>
+/// Log of tasks that were discarded rather than completed.
+pub struct DiscardedTaskList {
+    writer: csv::Writer<File>,
+}
+
+impl<Tasks, Completed, Discarded> Store<Tasks, Completed, Discarded>
 where
-    Tasks: CreateTask + RemoveTasks + ListTasks,
-    Completed: AddTasks,
+    Tasks: CreateTask + EditTask + MoveTask + RemoveTasks + ListTasks,
+    Completed: AddTasks + ListCompletedTasks + RemoveCompletedTasks,
+    Discarded: AddDiscardedTasks,
 {
-    pub fn new(tasks: Tasks, completed: Completed) -> Self {
-        Store { tasks, completed }
+    /// Create a store from its task lists, reading the completed tasks to build
+    /// the search index.
+    pub fn new(tasks: Tasks, completed: Completed, discarded: Discarded) -> Result<Self, Error> {
+        let mut index = search::Index::new();
+        for task in completed.list_completed()? {
+            index.insert(Document::from(&task));
+        }
+        for task in tasks.list() {
+            index.insert(Document::from(task));
+        }
+
+        Ok(Store {
+            tasks,
+            completed,
+            discarded,
+            index,
+        })
     }
 
     pub fn add(&mut self, task: NewTask) -> Result<TaskId, Error> {
-        self.tasks.create(task)
+        let id = self.tasks.create(task)?;
+        self.index_task(id);
+        Ok(id)
     }
 
-    pub fn complete(&mut self, task_ids: &[TaskId]) -> Result<(), Error> {
+    /// Move tasks to the completed list. Completing a task completes its subtasks
+    /// too. The next occurrence of recurring tasks is added to the active task list,
+    /// along with a copy of their subtasks.
+    ///
+    /// Returns the ids of the tasks that were completed, including subtasks.
+    pub fn complete(&mut self, task_ids: &[TaskId]) -> Result<Vec<TaskId>, Error> {
+        let today = Local::today().naive_local();
+        // Tasks completed together, such as a task and its subtasks, share a
+        // completion time
+        let completed_at = Utc::now().trunc_subsecs(0);
+        let task_ids = with_subtasks(self.tasks.list(), task_ids);
         let completed = &mut self.completed;
-        self.tasks
-            .remove(task_ids, |removed_tasks| completed.add(&removed_tasks))
+        let mut documents = Vec::new();
+        let mut next_documents = Vec::new();
+        self.tasks.remove(&task_ids, |removed_tasks| {
+            let occurrences = next_occurrences(&removed_tasks, &task_ids, today);
+            let mut completed_tasks = Vec::new();
+            let mut next = Vec::new();
+            for (&task, occurrence) in removed_tasks.iter().zip(occurrences) {
+                completed_tasks.push(CompletedTask {
+                    completed_at,
+                    next_id: occurrence.as_ref().map(|next| next.id),
+                    ..CompletedTask::from(task)
+                });
+                next.extend(occurrence);
+            }
+            documents = completed_tasks.iter().map(Document::from).collect();
+            next_documents = next.iter().map(Document::from).collect();
+            completed.add(&completed_tasks)?;
+            // The next occurrences are added along with the removal, so that a
+            // recurring task can't be completed without one
+            Ok(next)
+        })?;
+
+        let completed_ids = documents.iter().map(|document| document.id).collect();
+        for document in documents {
+            self.index.insert(document);
+        }
+        for document in next_documents {
+            self.index.insert(document);
+        }
+        Ok(completed_ids)
+    }
+
+    /// Remove tasks, and their subtasks, from the active task list without
+    /// completing them.
+    pub fn discard(&mut self, task_ids: &[TaskId], reason: Option<&str>) -> Result<(), Error> {
+        let task_ids = with_subtasks(self.tasks.list(), task_ids);
+        let discarded = &mut self.discarded;
+        let mut discarded_ids = Vec::new();
+        self.tasks.remove(&task_ids, |removed_tasks| {
+            discarded_ids = removed_tasks.iter().map(|task| task.id).collect();
+            discarded.add_discarded(&removed_tasks, reason)?;
+            Ok(Vec::new())
+        })?;
+
+        for id in discarded_ids {
+            self.index.remove(id);
+        }
+        Ok(())
+    }
+
+    pub fn edit(&mut self, task_id: TaskId, edit: impl FnOnce(&mut Task)) -> Result<(), Error> {
+        self.tasks.edit(task_id, edit)?;
+        self.index_task(task_id);
+        Ok(())
+    }
+
+    /// Change the position of a task in the active task list.
+    pub fn move_task(&mut self, task_id: TaskId, position: Position) -> Result<(), Error> {
+        self.tasks.move_task(task_id, position)
+    }
+
+    /// Move completed tasks back into the active task list. The occurrences added
+    /// when recurring tasks were completed are removed if they are still active, so
+    /// that undoing the completion doesn't leave two of the same task.
+    ///
+    /// Returns the ids of the tasks that were reopened.
+    pub fn reopen(&mut self, task_ids: &[TaskId]) -> Result<Vec<TaskId>, Error> {
+        let tasks = &mut self.tasks;
+        let mut occurrences = Vec::new();
+        let mut reopened_ids = Vec::new();
+        self.completed
+            .remove_completed(task_ids, |completed_tasks| {
+                // A task completed more than once comes back once, as it was when it
+                // was last completed
+                let mut seen = HashSet::new();
+                let mut reopened_tasks = completed_tasks
+                    .into_iter()
+                    .rev()
+                    .filter(|task| seen.insert(task.id))
+                    .collect::<Vec<_>>();
+                reopened_tasks.reverse();
+                reopened_ids = reopened_tasks.iter().map(|task| task.id).collect();
+                let active = tasks.list();
+                occurrences = reopened_tasks
+                    .iter()
+                    .filter_map(|task| task.next_id)
+                    .filter(|&next_id| active.iter().any(|task| task.id == next_id))
+                    .collect();
+                let reopened: Vec<Task> = reopened_tasks
+                    .into_iter()
+                    .map(Task::from)
+                    .map(|mut task| {
+                        // Subtasks whose parent is still completed are reopened on
+                        // their own
+                        if let Some(parent) = task.parent {
+                            if !task_ids.contains(&parent)
+                                && !active.iter().any(|task| task.id == parent)
+                            {
+                                task.parent = None;
+                            }
+                        }
+                        task
+                    })
+                    .collect();
+                tasks.remove(&occurrences, |_| Ok(reopened))
+            })?;
+
+        for id in occurrences {
+            self.index.remove(id);
+        }
+        for &id in &reopened_ids {
+            self.index_task(id);
+        }
+        Ok(reopened_ids)
+    }
+
+    /// The active tasks that aren't snoozed. Subtasks are hidden along with their
+    /// parent.
+    pub fn list(&self) -> Vec<&Task> {
+        let today = Local::today().naive_local();
+        self.all()
+            .iter()
+            .filter(|task| !self.is_hidden(task, today))
+            .collect()
+    }
+
+    /// The active tasks that are snoozed, or whose parent is.
+    pub fn snoozed(&self) -> Vec<&Task> {
+        let today = Local::today().naive_local();
+        self.all()
+            .iter()
+            .filter(|task| self.is_hidden(task, today))
+            .collect()
+    }
+
+    fn is_hidden(&self, task: &Task, today: NaiveDate) -> bool {
+        task.is_hidden(today)
+            || task.parent.map_or(false, |parent| {
+                self.all()
+                    .iter()
+                    .any(|other| other.id == parent && other.is_hidden(today))
+            })
+    }
+
+    /// Whether the task with id `task_id`, or a new task when `None`, can be made
+    /// a subtask of the task with id `parent_id`. Subtasks are only one level deep,
+    /// so the parent can't be a subtask and the task can't have subtasks of its
+    /// own.
+    pub fn can_nest(&self, task_id: Option<TaskId>, parent_id: TaskId) -> bool {
+        let tasks = self.all();
+        task_id != Some(parent_id)
+            && tasks
+                .iter()
+                .any(|task| task.id == parent_id && task.parent.is_none())
+            && task_id.map_or(true, |id| !tasks.iter().any(|task| task.parent == Some(id)))
     }
 
-    pub fn list(&self) -> &[Task] {
+    /// All active tasks, including those that are snoozed.
+    pub fn all(&self) -> &[Task] {
         self.tasks.list()
     }
+
+    /// Active and completed tasks matching `query`, see `search::Index::search`.
+    pub fn search(&self, query: &str) -> Vec<&Document> {
+        self.index.search(query)
+    }
+
+    /// Update the search index with the active task with id `task_id`.
+    fn index_task(&mut self, task_id: TaskId) {
+        if let Some(task) = self.tasks.list().iter().find(|task| task.id == task_id) {
+            self.index.insert(Document::from(task));
+        }
+    }
+
+    /// Completed tasks, most recently completed first.
+    pub fn completed(&self) -> Result<Vec<CompletedTask>, Error> {
+        let mut completed = self.completed.list_completed()?;
+        completed.reverse();
+        Ok(completed)
+    }
+}
+
+/// The tasks to add when `tasks` are completed together on `today`, by position in
+/// `tasks`. Recurring tasks are followed by their next occurrence, and the subtasks
+/// of a recurring task are copied to its next occurrence, recurring or not.
+/// Recurring subtasks completed without a recurring parent come back on their own.
+fn next_occurrences(tasks: &[&Task], task_ids: &[TaskId], today: NaiveDate) -> Vec<Option<Task>> {
+    let completed_parent = |task: &Task| task.parent.filter(|id| task_ids.contains(id));
+    let mut occurrences = tasks
+        .iter()
+        .map(|&task| match completed_parent(task) {
+            Some(_) => None,
+            None => task.next_occurrence(today).map(Task::from),
+        })
+        .collect::<Vec<_>>();
+    // Subtasks need the id of their parent's next occurrence, so are done once the
+    // others are
+    for (i, &task) in tasks.iter().enumerate() {
+        let parent = match completed_parent(task) {
+            Some(parent) => parent,
+            None => continue,
+        };
+        let next_parent = tasks
+            .iter()
+            .zip(&occurrences)
+            .find(|(parent_task, _)| parent_task.id == parent)
+            .and_then(|(_, occurrence)| occurrence.as_ref().map(|next| next.id));
+        let next = match next_parent {
+            Some(_) => task.next_occurrence(today).or_else(|| {
+                Some(NewTask {
+                    description: task.description.clone(),
+                    due: None,
+                    priority: task.priority,
+                    notes: task.notes.clone(),
+                    recurrence: None,
+                    parent: None,
+                })
+            }),
+            None => task.next_occurrence(today),
+        };
+        occurrences[i] = next.map(|next| {
+            Task::from(NewTask {
+                parent: next_parent,
+                ..next
+            })
+        });
+    }
+    occurrences
+}
+
+/// `task_ids` and the ids of the subtasks of those tasks in `tasks`.
+fn with_subtasks(tasks: &[Task], task_ids: &[TaskId]) -> Vec<TaskId> {
+    let mut ids = task_ids.to_vec();
+    ids.extend(
+        tasks
+            .iter()
+            .filter(|task| {
+                task.parent
+                    .map_or(false, |parent| task_ids.contains(&parent))
+            })
+            .filter(|task| !task_ids.contains(&task.id))
+            .map(|task| task.id),
+    );
+    ids
+}
+
+/// `tasks` with the task with id `task_id` moved to `position`, or `None` if either
+/// task is not present.
+fn reorder(tasks: &[Task], task_id: TaskId, position: Position) -> Option<Vec<Task>> {
+    let mut tasks = tasks.to_vec();
+    let from = tasks.iter().position(|task| task.id == task_id)?;
+    let task = tasks.remove(from);
+    let to = match position {
+        Position::Top => 0,
+        Position::Bottom => tasks.len(),
+        Position::Before(id) => tasks.iter().position(|task| task.id == id)?,
+        Position::After(id) => tasks.iter().position(|task| task.id == id)? + 1,
+    };
+    tasks.insert(to, task);
+    Some(tasks)
+}
+
+pub(crate) fn read_records<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, Error> {
+    match File::open(path) {
+        Ok(file) => {
+            let file = BufReader::new(file);
+            // Records written by older versions may have fewer columns
+            let mut rdr = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(file);
+            rdr.deserialize()
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(Error::from)
+        }
+        Err(err) => match err.kind() {
+            io::ErrorKind::NotFound => Ok(Vec::new()),
+            _ => Err(Error::from(err)),
+        },
+    }
+}
+
+fn open_append_writer(path: &Path) -> Result<csv::Writer<File>, Error> {
+    let mut options = OpenOptions::new();
+    let file = options.create(true).append(true).open(path)?;
+    let mut builder = csv::WriterBuilder::new();
+    Ok(builder.has_headers(false).from_writer(file))
+}
+
+fn write_records<T: Serialize>(records: &[T], file: &mut File) -> Result<(), Error> {
+    let mut builder = csv::WriterBuilder::new();
+    let mut writer = builder.has_headers(false).from_writer(file);
+    for record in records {
+        writer.serialize(record)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Replace the contents of the file at `path` with `records`.
+pub(crate) fn replace_records<T: Serialize>(path: &Path, records: &[T]) -> Result<(), Error> {
+    // Write to a temp file in the same directory as the target file, then move it
+    // into place
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&temp_path)?;
+        write_records(records, &mut file)?;
+    }
+    fs::rename(temp_path, path)?;
+
+    Ok(())
 }
 
 impl ReadWriteTaskList {
     pub fn new<P: AsRef<OsStr>>(path: P) -> Result<Self, Error> {
         // Attempt to read the records in from the file to populate the vec of tasks
         let path = Path::new(&path).to_owned();
-        let tasks = Self::read_tasks(&path)?;
+        let tasks = read_records(&path)?;
 
         Ok(ReadWriteTaskList { tasks, path })
     }
 
-    fn read_tasks(path: &Path) -> Result<Vec<Task>, Error> {
-        match File::open(path) {
-            Ok(file) => {
-                let file = BufReader::new(file);
-                let mut rdr = csv::ReaderBuilder::new()
-                    .has_headers(false)
-                    .from_reader(file);
-                rdr.deserialize()
-                    .collect::<Result<Vec<_>, _>>()
-                    .map_err(Error::from)
-            }
-            Err(err) => match err.kind() {
-                io::ErrorKind::NotFound => Ok(Vec::new()),
-                _ => return Err(Error::from(err)),
-            },
-        }
+    /// Replace the contents of the file with `tasks`.
+    fn replace(&self, tasks: &[Task]) -> Result<(), Error> {
+        replace_records(&self.path, tasks)
     }
 
-    fn write_tasks(tasks: &[&Task], file: &mut File) -> Result<(), Error> {
-        let mut builder = csv::WriterBuilder::new();
-        let mut writer = builder.has_headers(false).from_writer(file);
-        for &task in tasks {
-            writer.serialize(task)?;
-        }
+    fn append(&mut self, tasks: Vec<Task>) -> Result<(), Error> {
+        // Append new items to file
+        let mut options = OpenOptions::new();
+        let mut file = options.create(true).append(true).open(&self.path)?;
+        write_records(&tasks, &mut file)?;
+
+        self.tasks.extend(tasks);
 
-        writer.flush()?;
         Ok(())
     }
 }
@@ -124,23 +493,40 @@ impl ReadWriteTaskList {
 impl CreateTask for ReadWriteTaskList {
     fn create(&mut self, new_task: NewTask) -> Result<TaskId, Error> {
         // Add the new task to self, then append it to the file
-        let id = Ulid::generate();
-        let task = Task {
-            id,
-            description: new_task.description,
-        };
+        let task = Task::from(new_task);
+        let id = task.id;
+        self.append(vec![task])?;
 
-        // Append new item to file
-        let mut options = OpenOptions::new();
-        let file = options.create(true).append(true).open(&self.path)?;
-        let mut builder = csv::WriterBuilder::new();
-        let mut writer = builder.has_headers(false).from_writer(file);
-        writer.serialize(&task)?;
-        writer.flush()?;
+        Ok(id)
+    }
+}
+
+impl EditTask for ReadWriteTaskList {
+    fn edit(&mut self, task_id: TaskId, edit: impl FnOnce(&mut Task)) -> Result<(), Error> {
+        let mut tasks = self.tasks.clone();
+        match tasks.iter_mut().find(|task| task.id == task_id) {
+            Some(task) => edit(task),
+            None => return Ok(()),
+        }
 
-        self.tasks.push(task);
+        self.replace(&tasks)?;
+        self.tasks = tasks;
 
-        Ok(id)
+        Ok(())
+    }
+}
+
+impl MoveTask for ReadWriteTaskList {
+    fn move_task(&mut self, task_id: TaskId, position: Position) -> Result<(), Error> {
+        let tasks = match reorder(&self.tasks, task_id, position) {
+            Some(tasks) => tasks,
+            None => return Ok(()),
+        };
+
+        self.replace(&tasks)?;
+        self.tasks = tasks;
+
+        Ok(())
     }
 }
 
@@ -148,7 +534,7 @@ impl RemoveTasks for ReadWriteTaskList {
     fn remove(
         &mut self,
         task_ids: &[TaskId],
-        mut body: impl FnMut(Vec<&Task>) -> Result<(), Error>,
+        body: impl FnOnce(Vec<&Task>) -> Result<Vec<Task>, Error>,
     ) -> Result<(), Error> {
         // Open a temp file in the same directory as the target file
         let temp_path = self.path.with_extension("tmp");
@@ -164,18 +550,19 @@ impl RemoveTasks for ReadWriteTaskList {
                 .partition(|task| task_ids.contains(&task.id));
 
             // Write out all tasks
-            Self::write_tasks(&keep, &mut file)?;
+            write_records(&keep, &mut file)?;
 
-            // Call the body
-            body(remove)?;
-            keep
+            // Call the body, then write out the tasks it added
+            let added = body(remove)?;
+            write_records(&added, &mut file)?;
+            keep.into_iter().cloned().chain(added).collect()
         };
 
         // Move into place if body was successful
         fs::rename(temp_path, &self.path)?;
 
         // Update ourselves with the new task list
-        self.tasks = keep.into_iter().cloned().collect();
+        self.tasks = keep;
 
         Ok(())
     }
@@ -190,20 +577,81 @@ impl ListTasks for ReadWriteTaskList {
 impl AppendOnlyTaskList {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         // Attempt to open the file for appending
-        let mut options = OpenOptions::new();
-        let file = options.create(true).append(true).open(path)?;
-        let mut builder = csv::WriterBuilder::new();
-        let writer = builder.has_headers(false).from_writer(file);
+        let path = path.as_ref().to_owned();
+        let writer = open_append_writer(&path)?;
 
-        Ok(AppendOnlyTaskList { writer })
+        Ok(AppendOnlyTaskList { writer, path })
     }
 }
 
 impl AddTasks for AppendOnlyTaskList {
-    fn add(&mut self, tasks: &[&Task]) -> Result<(), Error> {
+    fn add(&mut self, tasks: &[CompletedTask]) -> Result<(), Error> {
+        for task in tasks {
+            self.writer.serialize(task)?;
+        }
+
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl ListCompletedTasks for AppendOnlyTaskList {
+    fn list_completed(&self) -> Result<Vec<CompletedTask>, Error> {
+        read_records(&self.path)
+    }
+}
+
+impl RemoveCompletedTasks for AppendOnlyTaskList {
+    fn remove_completed(
+        &mut self,
+        task_ids: &[TaskId],
+        mut body: impl FnMut(Vec<CompletedTask>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        // Removal is expected to be rare so the whole file is rewritten, much like
+        // the active task list.
+        let temp_path = self.path.with_extension("tmp");
+
+        {
+            let mut file = File::create(&temp_path)?;
+
+            let (remove, keep): (Vec<CompletedTask>, Vec<CompletedTask>) = self
+                .list_completed()?
+                .into_iter()
+                .partition(|task| task_ids.contains(&task.id));
+
+            write_records(&keep, &mut file)?;
+
+            body(remove)?;
+        }
+
+        // Move into place if body was successful
+        fs::rename(temp_path, &self.path)?;
+
+        // The writer still refers to the file that was replaced, reopen it
+        self.writer = open_append_writer(&self.path)?;
+
+        Ok(())
+    }
+}
+
+impl DiscardedTaskList {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let writer = open_append_writer(path.as_ref())?;
+
+        Ok(DiscardedTaskList { writer })
+    }
+}
+
+impl AddDiscardedTasks for DiscardedTaskList {
+    fn add_discarded(&mut self, tasks: &[&Task], reason: Option<&str>) -> Result<(), Error> {
+        let discarded_at = Utc::now().trunc_subsecs(0);
         for &task in tasks {
-            let completed_task = CompletedTask::from(task);
-            self.writer.serialize(completed_task)?;
+            self.writer.serialize(DiscardedTask {
+                id: task.id,
+                description: task.description.clone(),
+                discarded_at,
+                reason: reason.map(String::from),
+            })?;
         }
 
         self.writer.flush()?;
@@ -211,9 +659,26 @@ impl AddTasks for AppendOnlyTaskList {
     }
 }
 
+/// Discarded tasks are only recorded when there is somewhere to record them.
+impl<T: AddDiscardedTasks> AddDiscardedTasks for Option<T> {
+    fn add_discarded(&mut self, tasks: &[&Task], reason: Option<&str>) -> Result<(), Error> {
+        match self {
+            Some(discarded) => discarded.add_discarded(tasks, reason),
+            None => Ok(()),
+        }
+    }
+}
+
 impl NewTask {
     pub fn new(description: String) -> Self {
-        NewTask { description }
+        NewTask {
+            description,
+            due: None,
+            priority: Priority::None,
+            notes: None,
+            recurrence: None,
+            parent: None,
+        }
     }
 }
 
@@ -229,23 +694,72 @@ impl From<csv::Error> for Error {
     }
 }
 
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Sqlite(err)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Io(err) => err.fmt(f),
             Error::Csv(err) => err.fmt(f),
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite(err) => err.fmt(f),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
-impl<'task> From<&'task Task> for CompletedTask<'task> {
-    fn from(task: &'task Task) -> Self {
+impl From<NewTask> for Task {
+    fn from(new_task: NewTask) -> Self {
+        let id = Ulid::generate();
+        Task {
+            id,
+            description: new_task.description,
+            created_at: models::created_at_from_id(id),
+            due: new_task.due,
+            priority: new_task.priority,
+            notes: new_task.notes,
+            recurrence: new_task.recurrence,
+            hidden_until: None,
+            parent: new_task.parent,
+        }
+    }
+}
+
+impl From<CompletedTask> for Task {
+    fn from(task: CompletedTask) -> Self {
+        Task {
+            id: task.id,
+            description: task.description,
+            created_at: task.created_at,
+            due: task.due,
+            priority: task.priority,
+            notes: task.notes,
+            recurrence: task.recurrence,
+            hidden_until: None,
+            parent: task.parent,
+        }
+    }
+}
+
+impl From<&Task> for CompletedTask {
+    fn from(task: &Task) -> Self {
         CompletedTask {
             id: task.id,
-            description: &task.description,
+            description: task.description.clone(),
             completed_at: Utc::now().trunc_subsecs(0),
+            created_at: task.created_at,
+            due: task.due,
+            priority: task.priority,
+            notes: task.notes.clone(),
+            recurrence: task.recurrence,
+            parent: task.parent,
+            next_id: None,
         }
     }
 }
@@ -253,35 +767,530 @@ impl<'task> From<&'task Task> for CompletedTask<'task> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
 
     const TASKS_FILENAME: &str = "tasks.csv";
     const COMPLETED_FILENAME: &str = "completed.csv";
+    const DISCARDED_FILENAME: &str = "discarded.csv";
+
+    type TestStore = Store<ReadWriteTaskList, AppendOnlyTaskList, Option<DiscardedTaskList>>;
+
+    /// Open a store on the task files in `dir`, creating them if needed.
+    fn open_store(dir: &Path) -> TestStore {
+        let tasks = ReadWriteTaskList::new(dir.join(TASKS_FILENAME)).expect(TASKS_FILENAME);
+        let completed =
+            AppendOnlyTaskList::new(dir.join(COMPLETED_FILENAME)).expect(COMPLETED_FILENAME);
+        Store::new(tasks, completed, None).expect("store")
+    }
+
+    /// Open a store on empty task files in a new temporary directory.
+    fn test_store() -> (TempDir, TestStore) {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let store = open_store(testdir.path());
+        (testdir, store)
+    }
 
     #[test]
     fn test() {
         // TODO: Write tests for more scenarios
-        let testdir = tempfile::tempdir().expect("unable to create tempdir");
-        let (id1, id2, tasks_path, completed_path) = {
-            let tasks_path = testdir.path().join(TASKS_FILENAME);
-            let completed_path = testdir.path().join(COMPLETED_FILENAME);
-
-            let tasks = ReadWriteTaskList::new(&tasks_path).expect(TASKS_FILENAME);
-            let completed = AppendOnlyTaskList::new(&completed_path).expect(COMPLETED_FILENAME);
-            let mut store = Store::new(tasks, completed);
-
-            let task1 = NewTask::new(String::from("do a thing"));
-            let task2 = NewTask::new(String::from("do another thing"));
-            let id1 = store.add(task1).unwrap();
-            let id2 = store.add(task2).unwrap();
-            store.complete(&[id1]).expect("complete");
-            (id1, id2, tasks_path, completed_path)
-        };
+        let (testdir, mut store) = test_store();
+        let task1 = NewTask::new(String::from("do a thing"));
+        let task2 = NewTask::new(String::from("do another thing"));
+        let id1 = store.add(task1).unwrap();
+        let id2 = store.add(task2).unwrap();
+        store.complete(&[id1]).expect("complete");
 
         // Now check on the state of the files
-        let tasks_csv = fs::read_to_string(tasks_path).unwrap();
-        let completed_csv = fs::read_to_string(completed_path).unwrap();
-        assert_eq!(format!("{},do another thing\n", id2), tasks_csv);
+        let tasks_csv = fs::read_to_string(testdir.path().join(TASKS_FILENAME)).unwrap();
+        let completed_csv = fs::read_to_string(testdir.path().join(COMPLETED_FILENAME)).unwrap();
+        assert!(tasks_csv.starts_with(&format!("{},do another thing,", id2)));
+        assert_eq!(tasks_csv.lines().count(), 1);
         // TODO: test completed_at...
         assert!(completed_csv.starts_with(&format!("{},do a thing,", id1)));
     }
+
+    #[test]
+    fn test_completed() {
+        let (_testdir, mut store) = test_store();
+        assert!(store.completed().unwrap().is_empty());
+
+        let id1 = store.add(NewTask::new(String::from("first"))).unwrap();
+        let id2 = store.add(NewTask::new(String::from("second"))).unwrap();
+        store.complete(&[id1]).expect("complete");
+        store.complete(&[id2]).expect("complete");
+
+        // Most recently completed first
+        let completed = store.completed().unwrap();
+        let ids: Vec<_> = completed.iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![id2, id1]);
+        assert_eq!(completed[1].description, "first");
+    }
+
+    #[test]
+    fn test_created_at() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let tasks_path = testdir.path().join(TASKS_FILENAME);
+        let completed_path = testdir.path().join(COMPLETED_FILENAME);
+
+        // Rows written before creation times were recorded
+        let old_id: TaskId = "01E5S5SZ0Z7GDTCSRS0EG7SG9N".parse().unwrap();
+        fs::write(&tasks_path, format!("{},old task\n", old_id)).unwrap();
+        fs::write(
+            &completed_path,
+            format!("{},old completed task,2020-05-01T10:00:00Z\n", old_id),
+        )
+        .unwrap();
+
+        let mut store = open_store(testdir.path());
+        assert_eq!(
+            store.list()[0].created_at,
+            old_id.datetime().trunc_subsecs(0)
+        );
+        assert_eq!(
+            store.completed().unwrap()[0].created_at,
+            old_id.datetime().trunc_subsecs(0)
+        );
+
+        // New tasks record their creation time, which is kept when completed
+        let id = store.add(NewTask::new(String::from("new task"))).unwrap();
+        let created_at = store.list()[1].created_at;
+        store.complete(&[id]).expect("complete");
+        let completed = store.completed().unwrap();
+        assert_eq!(completed[0].id, id);
+        assert_eq!(completed[0].created_at, created_at);
+        let completed_csv = fs::read_to_string(&completed_path).unwrap();
+        assert!(completed_csv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .contains(&format!(",{},", created_at.format("%Y-%m-%dT%H:%M:%SZ"))));
+    }
+
+    #[test]
+    fn test_due() {
+        let (testdir, mut store) = test_store();
+        let tasks_path = testdir.path().join(TASKS_FILENAME);
+        let due = NaiveDate::from_ymd(2026, 11, 1);
+        let id = store
+            .add(NewTask {
+                description: String::from("pay rent"),
+                due: Some(due),
+                priority: Priority::None,
+                notes: None,
+                recurrence: None,
+                parent: None,
+            })
+            .unwrap();
+        store.complete(&[id]).expect("complete");
+        assert_eq!(store.completed().unwrap()[0].due, Some(due));
+        store.reopen(&[id]).expect("reopen");
+
+        // Reread the file
+        let tasks = ReadWriteTaskList::new(&tasks_path).expect(TASKS_FILENAME);
+        assert_eq!(tasks.list()[0].due, Some(due));
+    }
+
+    #[test]
+    fn test_priority() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let tasks_path = testdir.path().join(TASKS_FILENAME);
+
+        // Tasks stored before priorities were recorded don't have one
+        let old_id = Ulid::generate();
+        fs::write(
+            &tasks_path,
+            format!("{},old task,2026-10-01T09:00:00Z,\n", old_id),
+        )
+        .unwrap();
+
+        let mut store = open_store(testdir.path());
+        assert_eq!(store.list()[0].priority, Priority::None);
+
+        let id = store
+            .add(NewTask {
+                priority: Priority::High,
+                ..NewTask::new(String::from("urgent"))
+            })
+            .unwrap();
+        store.complete(&[id]).expect("complete");
+        assert_eq!(store.completed().unwrap()[0].priority, Priority::High);
+        store.reopen(&[id]).expect("reopen");
+
+        // Reread the file
+        let tasks = ReadWriteTaskList::new(&tasks_path).expect(TASKS_FILENAME);
+        assert_eq!(tasks.list()[0].priority, Priority::None);
+        assert_eq!(tasks.list()[1].priority, Priority::High);
+    }
+
+    #[test]
+    fn test_notes() {
+        let (testdir, mut store) = test_store();
+        let tasks_path = testdir.path().join(TASKS_FILENAME);
+        let completed_path = testdir.path().join(COMPLETED_FILENAME);
+        let notes = String::from("Why:\n\n* it, \"quoted\"\n* https://example.com/");
+        let id1 = store
+            .add(NewTask {
+                notes: Some(notes.clone()),
+                ..NewTask::new(String::from("read this"))
+            })
+            .unwrap();
+        let id2 = store.add(NewTask::new(String::from("no notes"))).unwrap();
+
+        // Reread the file
+        let tasks = ReadWriteTaskList::new(&tasks_path).expect(TASKS_FILENAME);
+        assert_eq!(tasks.list()[0].notes.as_ref(), Some(&notes));
+        assert_eq!(tasks.list()[1].notes, None);
+
+        store.complete(&[id1, id2]).expect("complete");
+        let completed = AppendOnlyTaskList::new(&completed_path).expect(COMPLETED_FILENAME);
+        let completed = completed.list_completed().unwrap();
+        assert_eq!(completed[0].notes.as_ref(), Some(&notes));
+        assert_eq!(completed[1].notes, None);
+    }
+
+    #[test]
+    fn test_recurrence() {
+        let (testdir, mut store) = test_store();
+        let tasks_path = testdir.path().join(TASKS_FILENAME);
+        let today = Local::today().naive_local();
+        let weekly = "weekly".parse().ok();
+        let id = store
+            .add(NewTask {
+                due: Some(today),
+                recurrence: weekly,
+                ..NewTask::new(String::from("newsletter roundup"))
+            })
+            .unwrap();
+        let other = store.add(NewTask::new(String::from("one off"))).unwrap();
+
+        store.complete(&[id, other]).expect("complete");
+        let completed = store.completed().unwrap();
+        assert_eq!(completed.len(), 2);
+        assert_eq!(completed[1].recurrence, weekly);
+        assert_eq!(store.list().len(), 1);
+        let next = &store.list()[0];
+        assert_ne!(next.id, id);
+        assert_eq!(next.description, "newsletter roundup");
+        assert_eq!(next.due, Some(today + chrono::Duration::weeks(1)));
+        assert_eq!(next.recurrence, weekly);
+
+        // Reread the file
+        let tasks = ReadWriteTaskList::new(&tasks_path).expect(TASKS_FILENAME);
+        assert_eq!(tasks.list()[0].recurrence, weekly);
+
+        // Undoing the completion removes the next occurrence
+        store.reopen(&[id]).expect("reopen");
+        let ids: Vec<_> = store.list().iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![id]);
+        assert_eq!(store.list()[0].due, Some(today));
+
+        // Only the occurrence added for the reopened task is removed, not a later
+        // one or a task that happens to match
+        store.complete(&[id]).expect("complete");
+        assert_eq!(
+            store.completed().unwrap()[0].next_id,
+            Some(store.list()[0].id)
+        );
+        let next = store.list()[0].id;
+        store.complete(&[next]).expect("complete");
+        let after_next = store.list()[0].id;
+        let copy = store
+            .add(NewTask {
+                recurrence: weekly,
+                ..NewTask::new(String::from("newsletter roundup"))
+            })
+            .unwrap();
+        store.reopen(&[id]).expect("reopen");
+        let mut ids: Vec<_> = store.list().iter().map(|task| task.id).collect();
+        let mut expected = vec![id, after_next, copy];
+        ids.sort();
+        expected.sort();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_subtasks() {
+        let (testdir, mut store) = test_store();
+        let tasks_path = testdir.path().join(TASKS_FILENAME);
+        let parent = store.add(NewTask::new(String::from("launch"))).unwrap();
+        let subtask = |parent| NewTask {
+            parent: Some(parent),
+            ..NewTask::new(String::from("step"))
+        };
+        let done = store.add(subtask(parent)).unwrap();
+        let open = store.add(subtask(parent)).unwrap();
+        let other = store.add(NewTask::new(String::from("other"))).unwrap();
+
+        assert!(store.can_nest(None, parent));
+        assert!(store.can_nest(Some(other), parent));
+        assert!(!store.can_nest(None, done), "subtasks can't have subtasks");
+        assert!(!store.can_nest(Some(parent), other), "task has subtasks");
+        assert!(!store.can_nest(Some(other), other));
+
+        // Completing the parent completes its remaining subtasks, at the same time
+        store.complete(&[done]).expect("complete");
+        let completed_ids = store.complete(&[parent]).expect("complete");
+        assert_eq!(completed_ids, vec![parent, open]);
+        let ids: Vec<_> = store.list().iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![other]);
+        let completed = store.completed().unwrap();
+        assert_eq!(completed.len(), 3);
+        assert_eq!(completed[0].completed_at, completed[1].completed_at);
+        assert!(completed
+            .iter()
+            .all(|task| task.id == parent || task.parent == Some(parent)));
+
+        // Undoing the completion reopens the subtasks with the parent
+        store.reopen(&completed_ids).expect("reopen");
+        let tasks = ReadWriteTaskList::new(&tasks_path).expect(TASKS_FILENAME);
+        let parents: Vec<_> = tasks
+            .list()
+            .iter()
+            .map(|task| (task.id, task.parent))
+            .collect();
+        assert_eq!(
+            parents,
+            vec![(other, None), (parent, None), (open, Some(parent))]
+        );
+        assert_eq!(store.completed().unwrap()[0].id, done);
+
+        // A subtask reopened without its parent is a task on its own
+        store.complete(&[parent]).expect("complete");
+        store.reopen(&[done]).expect("reopen");
+        let reopened = store
+            .list()
+            .into_iter()
+            .find(|task| task.id == done)
+            .unwrap();
+        assert_eq!(reopened.parent, None);
+    }
+
+    #[test]
+    fn test_recurring_subtasks() {
+        let (_testdir, mut store) = test_store();
+        let today = Local::today().naive_local();
+        let weekly = "weekly".parse().ok();
+        let daily = "daily".parse().ok();
+        let parent = store
+            .add(NewTask {
+                recurrence: weekly,
+                ..NewTask::new(String::from("weekly review"))
+            })
+            .unwrap();
+        let subtask = |description: &str, recurrence| NewTask {
+            recurrence,
+            parent: Some(parent),
+            ..NewTask::new(String::from(description))
+        };
+        let inbox = store.add(subtask("empty inbox", None)).unwrap();
+        let water = store.add(subtask("water plants", daily)).unwrap();
+
+        // The next occurrence comes with a copy of the subtasks
+        let completed_ids = store.complete(&[parent]).expect("complete");
+        assert_eq!(completed_ids.len(), 3);
+        let tasks = store.list();
+        assert_eq!(tasks.len(), 3);
+        let next = tasks.iter().find(|task| task.parent.is_none()).unwrap();
+        assert_eq!(next.description, "weekly review");
+        let subtasks = tasks
+            .iter()
+            .filter(|task| task.parent == Some(next.id))
+            .map(|task| (task.description.as_str(), task.recurrence, task.due))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subtasks,
+            vec![
+                ("empty inbox", None, None),
+                ("water plants", daily, Some(today.succ()))
+            ]
+        );
+
+        // Undoing the completion removes the copies too
+        store.reopen(&completed_ids).expect("reopen");
+        let mut ids: Vec<_> = store.list().iter().map(|task| task.id).collect();
+        let mut expected = vec![parent, inbox, water];
+        ids.sort();
+        expected.sort();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_discard() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let tasks_path = testdir.path().join(TASKS_FILENAME);
+        let completed_path = testdir.path().join(COMPLETED_FILENAME);
+        let discarded_path = testdir.path().join(DISCARDED_FILENAME);
+
+        let tasks = ReadWriteTaskList::new(&tasks_path).expect(TASKS_FILENAME);
+        let completed = AppendOnlyTaskList::new(&completed_path).expect(COMPLETED_FILENAME);
+        let discarded = DiscardedTaskList::new(&discarded_path).expect(DISCARDED_FILENAME);
+        let mut store = Store::new(tasks, completed, Some(discarded)).expect("store");
+
+        let id1 = store.add(NewTask::new(String::from("mistake"))).unwrap();
+        let id2 = store.add(NewTask::new(String::from("keep"))).unwrap();
+        store.discard(&[id1], Some("duplicate")).expect("discard");
+
+        let ids: Vec<_> = store.list().iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![id2]);
+        assert!(store.completed().unwrap().is_empty());
+        let discarded_csv = fs::read_to_string(&discarded_path).unwrap();
+        assert!(discarded_csv.starts_with(&format!("{},mistake,", id1)));
+        assert!(discarded_csv.ends_with(",duplicate\n"));
+    }
+
+    #[test]
+    fn test_discard_subtasks() {
+        let (_testdir, mut store) = test_store();
+        let parent = store.add(NewTask::new(String::from("abandoned"))).unwrap();
+        store
+            .add(NewTask {
+                parent: Some(parent),
+                ..NewTask::new(String::from("step"))
+            })
+            .unwrap();
+        store.discard(&[parent], None).expect("discard");
+        assert!(store.all().is_empty());
+    }
+
+    #[test]
+    fn test_edit() {
+        let (testdir, mut store) = test_store();
+        let tasks_path = testdir.path().join(TASKS_FILENAME);
+
+        let id1 = store.add(NewTask::new(String::from("tpyo"))).unwrap();
+        let id2 = store.add(NewTask::new(String::from("fine"))).unwrap();
+        store
+            .edit(id1, |task| task.description = String::from("typo"))
+            .expect("edit");
+
+        assert_eq!(store.list()[0].description, "typo");
+        let tasks_csv = fs::read_to_string(&tasks_path).unwrap();
+        let lines: Vec<_> = tasks_csv.lines().collect();
+        assert!(lines[0].starts_with(&format!("{},typo,", id1)));
+        assert!(lines[1].starts_with(&format!("{},fine,", id2)));
+    }
+
+    #[test]
+    fn test_move_task() {
+        let (testdir, mut store) = test_store();
+        let tasks_path = testdir.path().join(TASKS_FILENAME);
+
+        let id1 = store.add(NewTask::new(String::from("one"))).unwrap();
+        let id2 = store.add(NewTask::new(String::from("two"))).unwrap();
+        let id3 = store.add(NewTask::new(String::from("three"))).unwrap();
+        let ids = |store: &Store<_, _, _>| -> Vec<_> {
+            store.list().iter().map(|task| task.id).collect()
+        };
+
+        store.move_task(id3, Position::Top).expect("move");
+        assert_eq!(ids(&store), vec![id3, id1, id2]);
+        store.move_task(id3, Position::After(id1)).expect("move");
+        assert_eq!(ids(&store), vec![id1, id3, id2]);
+        store.move_task(id2, Position::Before(id1)).expect("move");
+        assert_eq!(ids(&store), vec![id2, id1, id3]);
+        store.move_task(id2, Position::Bottom).expect("move");
+        assert_eq!(ids(&store), vec![id1, id3, id2]);
+
+        // Moving relative to itself or a missing task changes nothing
+        store.move_task(id1, Position::Before(id1)).expect("move");
+        store
+            .move_task(id1, Position::After(Ulid::generate()))
+            .expect("move");
+        assert_eq!(ids(&store), vec![id1, id3, id2]);
+
+        // The order is persisted
+        let tasks = ReadWriteTaskList::new(&tasks_path).expect(TASKS_FILENAME);
+        let reread: Vec<_> = tasks.list().iter().map(|task| task.id).collect();
+        assert_eq!(reread, vec![id1, id3, id2]);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let (testdir, mut store) = test_store();
+        let tasks_path = testdir.path().join(TASKS_FILENAME);
+
+        let id1 = store.add(NewTask::new(String::from("oops"))).unwrap();
+        let id2 = store.add(NewTask::new(String::from("done"))).unwrap();
+        store.complete(&[id1, id2]).expect("complete");
+        assert_eq!(store.reopen(&[id1]).expect("reopen"), vec![id1]);
+        // Already reopened
+        assert!(store.reopen(&[id1]).expect("reopen").is_empty());
+
+        let ids: Vec<_> = store.list().iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![id1]);
+        let tasks_csv = fs::read_to_string(&tasks_path).unwrap();
+        assert!(tasks_csv.starts_with(&format!("{},oops,", id1)));
+        assert_eq!(tasks_csv.lines().count(), 1);
+
+        // Completing again appends to the rewritten completed file
+        store.complete(&[id1]).expect("complete");
+        let completed: Vec<_> = store
+            .completed()
+            .unwrap()
+            .iter()
+            .map(|task| task.id)
+            .collect();
+        assert_eq!(completed, vec![id1, id2]);
+    }
+    #[test]
+    fn test_snooze() {
+        let (testdir, mut store) = test_store();
+        let tasks_path = testdir.path().join(TASKS_FILENAME);
+        let today = Local::today().naive_local();
+        let id1 = store.add(NewTask::new(String::from("later"))).unwrap();
+        let id2 = store.add(NewTask::new(String::from("now"))).unwrap();
+        store
+            .edit(id1, |task| task.hidden_until = Some(today.succ()))
+            .expect("edit");
+
+        let ids = |tasks: Vec<&Task>| -> Vec<_> { tasks.iter().map(|task| task.id).collect() };
+        assert_eq!(ids(store.list()), vec![id2]);
+        assert_eq!(ids(store.snoozed()), vec![id1]);
+        assert_eq!(store.all().len(), 2);
+
+        // Reread the file
+        let tasks = ReadWriteTaskList::new(&tasks_path).expect(TASKS_FILENAME);
+        assert_eq!(tasks.list()[0].hidden_until, Some(today.succ()));
+
+        // Tasks are shown again on the day they were snoozed until
+        store
+            .edit(id1, |task| task.hidden_until = Some(today))
+            .expect("edit");
+        assert_eq!(ids(store.list()), vec![id1, id2]);
+        assert!(store.snoozed().is_empty());
+    }
+
+    #[test]
+    fn test_search() {
+        let (testdir, mut store) = test_store();
+        let post = store
+            .add(NewTask {
+                notes: Some(String::from("Via the newsletter")),
+                ..NewTask::new(String::from("https://example.com/async-traits"))
+            })
+            .unwrap();
+        let draft = store.add(NewTask::new(String::from("Draft post"))).unwrap();
+        let ids = |store: &Store<_, _, _>, query| -> Vec<_> {
+            store.search(query).iter().map(|doc| doc.id).collect()
+        };
+
+        store.complete(&[post]).expect("complete");
+        assert_eq!(ids(&store, "newsletter"), vec![post]);
+        assert!(store.search("async")[0].completed_at.is_some());
+        store
+            .edit(draft, |task| task.description = String::from("Draft talk"))
+            .expect("edit");
+        assert!(ids(&store, "post").is_empty());
+        assert_eq!(ids(&store, "talk"), vec![draft]);
+
+        // The index is built from both lists when the store is opened
+        let mut store = open_store(testdir.path());
+        assert_eq!(ids(&store, "example"), vec![post]);
+        assert_eq!(ids(&store, "draft"), vec![draft]);
+
+        store.reopen(&[post]).expect("reopen");
+        assert!(store.search("async")[0].completed_at.is_none());
+        store.discard(&[draft], None).expect("discard");
+        assert!(ids(&store, "draft").is_empty());
+    }
 }
@@ -0,0 +1,758 @@
+//! SQLite storage.
+//!
+//! Active and completed tasks are stored in a single database. The two task lists
+//! share one connection so that removing tasks from one list and adding them to the
+//! other happens within a single transaction.
+
+use std::error::Error as StdError;
+use std::path::Path;
+use std::slice;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Row};
+
+use chrono::prelude::*;
+
+use super::{
+    AddDiscardedTasks, AddTasks, CreateTask, EditTask, Error, ListCompletedTasks, ListTasks,
+    MoveTask, RemoveCompletedTasks, RemoveTasks,
+};
+use crate::models::{self, CompletedTask, NewTask, Position, Task, TaskId, Timestamp};
+
+type SharedConnection = Arc<Mutex<Connection>>;
+
+/// Schema migrations, applied in order. The number of migrations applied is tracked
+/// in the `user_version` pragma.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE tasks (
+        id TEXT PRIMARY KEY NOT NULL,
+        description TEXT NOT NULL
+    );
+    CREATE TABLE completed (
+        id TEXT NOT NULL,
+        description TEXT NOT NULL,
+        completed_at TEXT NOT NULL
+    );
+    ",
+    "
+    CREATE TABLE discarded (
+        id TEXT NOT NULL,
+        description TEXT NOT NULL,
+        discarded_at TEXT NOT NULL,
+        reason TEXT
+    );
+    ",
+    // Rows without a creation time use the time from the id
+    "
+    ALTER TABLE tasks ADD COLUMN created_at TEXT;
+    ALTER TABLE completed ADD COLUMN created_at TEXT;
+    ",
+    "
+    ALTER TABLE tasks ADD COLUMN due TEXT;
+    ALTER TABLE completed ADD COLUMN due TEXT;
+    ",
+    "
+    ALTER TABLE tasks ADD COLUMN priority TEXT;
+    ALTER TABLE completed ADD COLUMN priority TEXT;
+    ",
+    "
+    ALTER TABLE tasks ADD COLUMN notes TEXT;
+    ALTER TABLE completed ADD COLUMN notes TEXT;
+    ",
+    "
+    ALTER TABLE tasks ADD COLUMN recurrence TEXT;
+    ALTER TABLE completed ADD COLUMN recurrence TEXT;
+    ",
+    "ALTER TABLE tasks ADD COLUMN hidden_until TEXT;",
+    "
+    ALTER TABLE tasks ADD COLUMN parent TEXT;
+    ALTER TABLE completed ADD COLUMN parent TEXT;
+    ",
+    "ALTER TABLE completed ADD COLUMN next_id TEXT;",
+    // Tasks were previously kept in order by rowid
+    "
+    ALTER TABLE tasks ADD COLUMN position INTEGER;
+    UPDATE tasks SET position = rowid;
+    ",
+];
+
+pub struct TaskList {
+    conn: SharedConnection,
+    tasks: Vec<Task>,
+}
+
+pub struct CompletedTaskList {
+    conn: SharedConnection,
+}
+
+pub struct DiscardedTaskList {
+    conn: SharedConnection,
+}
+
+/// Open (creating if necessary) the database at `path`.
+pub fn open<P: AsRef<Path>>(
+    path: P,
+) -> Result<(TaskList, CompletedTaskList, DiscardedTaskList), Error> {
+    let conn = Connection::open(path)?;
+    migrate(&conn)?;
+    let tasks = read_tasks(&conn)?;
+    let conn = Arc::new(Mutex::new(conn));
+
+    Ok((
+        TaskList {
+            conn: Arc::clone(&conn),
+            tasks,
+        },
+        CompletedTaskList {
+            conn: Arc::clone(&conn),
+        },
+        DiscardedTaskList { conn },
+    ))
+}
+
+fn migrate(conn: &Connection) -> Result<(), Error> {
+    let version: usize = conn.query_row("PRAGMA user_version", params![], |row| {
+        row.get::<_, i64>(0).map(|version| version as usize)
+    })?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        conn.execute_batch(&format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            migration,
+            i + 1
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Run `body` in a transaction, unless one is already in progress in which case
+/// `body` becomes part of it.
+fn transaction<T>(
+    conn: &Mutex<Connection>,
+    body: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    if !conn.lock().unwrap().is_autocommit() {
+        return body();
+    }
+
+    conn.lock().unwrap().execute_batch("BEGIN IMMEDIATE")?;
+    match body() {
+        Ok(value) => {
+            conn.lock().unwrap().execute_batch("COMMIT")?;
+            Ok(value)
+        }
+        Err(err) => {
+            if let Err(rollback_err) = conn.lock().unwrap().execute_batch("ROLLBACK") {
+                log::error!("unable to roll back transaction: {}", rollback_err);
+            }
+            Err(err)
+        }
+    }
+}
+
+fn read_tasks(conn: &Connection) -> Result<Vec<Task>, Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, description, created_at, due, priority, notes, recurrence, hidden_until,
+                parent
+         FROM tasks ORDER BY position",
+    )?;
+    let tasks = stmt
+        .query_map(params![], |row| {
+            let id = parse_column(row, 0)?;
+            Ok(Task {
+                id,
+                description: row.get(1)?,
+                created_at: parse_created_at(row, 2, id)?,
+                due: parse_optional_column(row, 3)?,
+                priority: parse_optional_column(row, 4)?.unwrap_or_default(),
+                notes: row.get(5)?,
+                recurrence: parse_optional_column(row, 6)?,
+                hidden_until: parse_optional_column(row, 7)?,
+                parent: parse_optional_column(row, 8)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(tasks)
+}
+
+fn insert_tasks(conn: &Connection, tasks: &[Task]) -> Result<(), Error> {
+    let mut stmt = conn.prepare(
+        "INSERT INTO tasks
+         (id, description, created_at, due, priority, notes, recurrence, hidden_until, parent,
+          position)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9,
+                 (SELECT IFNULL(MAX(position), 0) + 1 FROM tasks))",
+    )?;
+    for task in tasks {
+        stmt.execute(params![
+            task.id.to_string(),
+            task.description,
+            task.created_at.to_rfc3339(),
+            task.due.map(|due| due.to_string()),
+            task.priority.as_str(),
+            task.notes,
+            task.recurrence.map(|recurrence| recurrence.to_string()),
+            task.hidden_until.map(|until| until.to_string()),
+            task.parent.map(|parent| parent.to_string())
+        ])?;
+    }
+    Ok(())
+}
+
+fn delete_tasks(conn: &Connection, table: &str, task_ids: &[TaskId]) -> Result<(), Error> {
+    let mut stmt = conn.prepare(&format!("DELETE FROM {} WHERE id = ?1", table))?;
+    for id in task_ids {
+        stmt.execute(params![id.to_string()])?;
+    }
+    Ok(())
+}
+
+fn parse_column<T>(row: &Row, idx: usize) -> rusqlite::Result<T>
+where
+    T: FromStr,
+    T::Err: StdError + Send + Sync + 'static,
+{
+    let value: String = row.get(idx)?;
+    value
+        .parse()
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(err)))
+}
+
+fn parse_optional_column<T>(row: &Row, idx: usize) -> rusqlite::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: StdError + Send + Sync + 'static,
+{
+    match row.get::<_, Option<String>>(idx)? {
+        Some(_) => parse_column(row, idx).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Parse the optional `created_at` column, falling back on the time from the id.
+fn parse_created_at(row: &Row, idx: usize, id: TaskId) -> rusqlite::Result<Timestamp> {
+    parse_optional_column(row, idx)
+        .map(|created_at| created_at.unwrap_or_else(|| models::created_at_from_id(id)))
+}
+
+impl CreateTask for TaskList {
+    fn create(&mut self, new_task: NewTask) -> Result<TaskId, Error> {
+        let task = Task::from(new_task);
+        let id = task.id;
+        insert_tasks(&self.conn.lock().unwrap(), slice::from_ref(&task))?;
+        self.tasks.push(task);
+
+        Ok(id)
+    }
+}
+
+impl EditTask for TaskList {
+    fn edit(&mut self, task_id: TaskId, edit: impl FnOnce(&mut Task)) -> Result<(), Error> {
+        let task = match self.tasks.iter_mut().find(|task| task.id == task_id) {
+            Some(task) => task,
+            None => return Ok(()),
+        };
+        let mut edited = task.clone();
+        edit(&mut edited);
+
+        self.conn.lock().unwrap().execute(
+            "UPDATE tasks
+             SET description = ?2, due = ?3, priority = ?4, notes = ?5, recurrence = ?6,
+                 hidden_until = ?7, parent = ?8
+             WHERE id = ?1",
+            params![
+                edited.id.to_string(),
+                edited.description,
+                edited.due.map(|due| due.to_string()),
+                edited.priority.as_str(),
+                edited.notes,
+                edited.recurrence.map(|recurrence| recurrence.to_string()),
+                edited.hidden_until.map(|until| until.to_string()),
+                edited.parent.map(|parent| parent.to_string())
+            ],
+        )?;
+        *task = edited;
+
+        Ok(())
+    }
+}
+
+impl MoveTask for TaskList {
+    fn move_task(&mut self, task_id: TaskId, position: Position) -> Result<(), Error> {
+        let tasks = match super::reorder(&self.tasks, task_id, position) {
+            Some(tasks) => tasks,
+            None => return Ok(()),
+        };
+
+        // NOTE(unwrap): reorder only succeeds when the task is present
+        let from = self
+            .tasks
+            .iter()
+            .position(|task| task.id == task_id)
+            .unwrap();
+        let to = tasks.iter().position(|task| task.id == task_id).unwrap();
+        if from != to {
+            // The task takes the position of the one it replaces, and those in
+            // between shift by one to make room
+            let replaced = self.tasks[to].id;
+            let conn = &self.conn;
+            transaction(conn, || {
+                let conn = conn.lock().unwrap();
+                let position_of = |id: TaskId| {
+                    conn.query_row(
+                        "SELECT position FROM tasks WHERE id = ?1",
+                        params![id.to_string()],
+                        |row| row.get::<_, i64>(0),
+                    )
+                };
+                let (old, new) = (position_of(task_id)?, position_of(replaced)?);
+                if new < old {
+                    conn.execute(
+                        "UPDATE tasks SET position = position + 1
+                         WHERE position >= ?1 AND position < ?2",
+                        params![new, old],
+                    )?;
+                } else {
+                    conn.execute(
+                        "UPDATE tasks SET position = position - 1
+                         WHERE position > ?1 AND position <= ?2",
+                        params![old, new],
+                    )?;
+                }
+                conn.execute(
+                    "UPDATE tasks SET position = ?2 WHERE id = ?1",
+                    params![task_id.to_string(), new],
+                )?;
+                Ok(())
+            })?;
+        }
+        self.tasks = tasks;
+
+        Ok(())
+    }
+}
+
+impl RemoveTasks for TaskList {
+    fn remove(
+        &mut self,
+        task_ids: &[TaskId],
+        body: impl FnOnce(Vec<&Task>) -> Result<Vec<Task>, Error>,
+    ) -> Result<(), Error> {
+        let conn = &self.conn;
+        let (remove, keep): (Vec<&Task>, Vec<&Task>) = self
+            .tasks
+            .iter()
+            .partition(|task| task_ids.contains(&task.id));
+
+        // The body is called within the transaction, so if it also writes to the
+        // database the whole operation succeeds or fails together.
+        let added = transaction(conn, || {
+            let ids = remove.iter().map(|task| task.id).collect::<Vec<_>>();
+            delete_tasks(&conn.lock().unwrap(), "tasks", &ids)?;
+            let added = body(remove)?;
+            insert_tasks(&conn.lock().unwrap(), &added)?;
+            Ok(added)
+        })?;
+
+        self.tasks = keep.into_iter().cloned().chain(added).collect();
+
+        Ok(())
+    }
+}
+
+impl ListTasks for TaskList {
+    fn list(&self) -> &[Task] {
+        self.tasks.as_slice()
+    }
+}
+
+impl AddTasks for CompletedTaskList {
+    fn add(&mut self, tasks: &[CompletedTask]) -> Result<(), Error> {
+        let conn = &self.conn;
+        transaction(conn, || {
+            let conn = conn.lock().unwrap();
+            let mut stmt = conn.prepare(
+                "INSERT INTO completed
+                 (id, description, completed_at, created_at, due, priority, notes, recurrence,
+                  parent, next_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            )?;
+            for completed_task in tasks {
+                stmt.execute(params![
+                    completed_task.id.to_string(),
+                    completed_task.description,
+                    completed_task.completed_at.to_rfc3339(),
+                    completed_task.created_at.to_rfc3339(),
+                    completed_task.due.map(|due| due.to_string()),
+                    completed_task.priority.as_str(),
+                    completed_task.notes,
+                    completed_task
+                        .recurrence
+                        .map(|recurrence| recurrence.to_string()),
+                    completed_task.parent.map(|parent| parent.to_string()),
+                    completed_task.next_id.map(|next_id| next_id.to_string())
+                ])?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl ListCompletedTasks for CompletedTaskList {
+    fn list_completed(&self) -> Result<Vec<CompletedTask>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, description, completed_at, created_at, due, priority, notes, recurrence,
+                    parent, next_id
+             FROM completed ORDER BY rowid",
+        )?;
+        let tasks = stmt
+            .query_map(params![], |row| {
+                let id = parse_column(row, 0)?;
+                Ok(CompletedTask {
+                    id,
+                    description: row.get(1)?,
+                    completed_at: parse_column(row, 2)?,
+                    created_at: parse_created_at(row, 3, id)?,
+                    due: parse_optional_column(row, 4)?,
+                    priority: parse_optional_column(row, 5)?.unwrap_or_default(),
+                    notes: row.get(6)?,
+                    recurrence: parse_optional_column(row, 7)?,
+                    parent: parse_optional_column(row, 8)?,
+                    next_id: parse_optional_column(row, 9)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tasks)
+    }
+}
+
+impl RemoveCompletedTasks for CompletedTaskList {
+    fn remove_completed(
+        &mut self,
+        task_ids: &[TaskId],
+        mut body: impl FnMut(Vec<CompletedTask>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let conn = &self.conn;
+        let remove = self
+            .list_completed()?
+            .into_iter()
+            .filter(|task| task_ids.contains(&task.id))
+            .collect::<Vec<_>>();
+
+        transaction(conn, || {
+            let ids = remove.iter().map(|task| task.id).collect::<Vec<_>>();
+            delete_tasks(&conn.lock().unwrap(), "completed", &ids)?;
+            body(remove)
+        })
+    }
+}
+
+impl AddDiscardedTasks for DiscardedTaskList {
+    fn add_discarded(&mut self, tasks: &[&Task], reason: Option<&str>) -> Result<(), Error> {
+        let conn = &self.conn;
+        let discarded_at = Utc::now().trunc_subsecs(0).to_rfc3339();
+        transaction(conn, || {
+            let conn = conn.lock().unwrap();
+            let mut stmt = conn.prepare(
+                "INSERT INTO discarded (id, description, discarded_at, reason)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for &task in tasks {
+                stmt.execute(params![
+                    task.id.to_string(),
+                    task.description,
+                    discarded_at,
+                    reason
+                ])?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Store;
+
+    const DATABASE_FILENAME: &str = "leaf.sqlite";
+
+    #[test]
+    fn test() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let path = testdir.path().join(DATABASE_FILENAME);
+        let (id1, id2) = {
+            let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+            let mut store = Store::new(tasks, completed, discarded).expect("store");
+
+            let id1 = store.add(NewTask::new(String::from("do a thing"))).unwrap();
+            let id2 = store
+                .add(NewTask::new(String::from("do another thing")))
+                .unwrap();
+            store.complete(&[id1]).expect("complete");
+            (id1, id2)
+        };
+
+        // Reopen the database and check its contents
+        let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+        let mut store = Store::new(tasks, completed, discarded).expect("store");
+        let ids: Vec<_> = store.list().iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![id2]);
+        let completed = store.completed().unwrap();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].id, id1);
+        assert_eq!(completed[0].description, "do a thing");
+        assert_eq!(completed[0].created_at, models::created_at_from_id(id1));
+
+        store
+            .edit(id2, |task| task.description = String::from("edited"))
+            .expect("edit");
+        assert_eq!(store.list()[0].description, "edited");
+
+        store.reopen(&[id1]).expect("reopen");
+        let ids: Vec<_> = store.list().iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![id2, id1]);
+        assert!(store.completed().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_move_task() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let path = testdir.path().join(DATABASE_FILENAME);
+        let (id1, id2, id3, id4) = {
+            let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+            let mut store = Store::new(tasks, completed, discarded).expect("store");
+
+            let id1 = store.add(NewTask::new(String::from("first"))).unwrap();
+            let id2 = store.add(NewTask::new(String::from("second"))).unwrap();
+            let id3 = store.add(NewTask::new(String::from("urgent"))).unwrap();
+            let id4 = store.add(NewTask::new(String::from("last"))).unwrap();
+            store.move_task(id3, Position::Top).expect("move");
+            store.move_task(id1, Position::After(id2)).expect("move");
+            (id1, id2, id3, id4)
+        };
+
+        let (tasks, _, _) = open(&path).expect(DATABASE_FILENAME);
+        let ids: Vec<_> = tasks.list().iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![id3, id2, id1, id4]);
+        assert_eq!(tasks.list()[0].description, "urgent");
+
+        // Tasks outside the range that moved keep their position
+        let conn = Connection::open(&path).unwrap();
+        let position: i64 = conn
+            .query_row(
+                "SELECT position FROM tasks WHERE id = ?1",
+                params![id4.to_string()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(position, 4);
+    }
+
+    #[test]
+    fn test_notes() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let path = testdir.path().join(DATABASE_FILENAME);
+        let notes = String::from("first line\nsecond line");
+        let id = {
+            let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+            let mut store = Store::new(tasks, completed, discarded).expect("store");
+
+            let id = store.add(NewTask::new(String::from("noted"))).unwrap();
+            store
+                .edit(id, |task| task.notes = Some(notes.clone()))
+                .expect("edit");
+            store.complete(&[id]).expect("complete");
+            id
+        };
+
+        let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+        let mut store = Store::new(tasks, completed, discarded).expect("store");
+        assert_eq!(store.completed().unwrap()[0].notes.as_ref(), Some(&notes));
+        store.reopen(&[id]).expect("reopen");
+        assert_eq!(store.list()[0].notes.as_ref(), Some(&notes));
+    }
+
+    #[test]
+    fn test_recurrence() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let path = testdir.path().join(DATABASE_FILENAME);
+        let monthly = "monthly".parse().ok();
+        {
+            let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+            let mut store = Store::new(tasks, completed, discarded).expect("store");
+
+            let id = store
+                .add(NewTask {
+                    recurrence: monthly,
+                    ..NewTask::new(String::from("dependency review"))
+                })
+                .unwrap();
+            store.complete(&[id]).expect("complete");
+        }
+
+        let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+        let store = Store::new(tasks, completed, discarded).expect("store");
+        assert_eq!(store.completed().unwrap()[0].recurrence, monthly);
+        assert_eq!(store.list().len(), 1);
+        assert_eq!(
+            store.completed().unwrap()[0].next_id,
+            Some(store.list()[0].id)
+        );
+        assert_eq!(store.list()[0].recurrence, monthly);
+        assert!(store.list()[0].due.is_some());
+    }
+
+    #[test]
+    fn test_subtasks() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let path = testdir.path().join(DATABASE_FILENAME);
+        let (parent, subtask) = {
+            let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+            let mut store = Store::new(tasks, completed, discarded).expect("store");
+
+            let parent = store.add(NewTask::new(String::from("launch"))).unwrap();
+            let subtask = store
+                .add(NewTask {
+                    parent: Some(parent),
+                    ..NewTask::new(String::from("step"))
+                })
+                .unwrap();
+            (parent, subtask)
+        };
+
+        let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+        let mut store = Store::new(tasks, completed, discarded).expect("store");
+        assert_eq!(store.list()[1].parent, Some(parent));
+        let completed_ids = store.complete(&[parent]).expect("complete");
+        assert!(store.list().is_empty());
+        let completed = store.completed().unwrap();
+        assert_eq!(completed.len(), 2);
+        assert_eq!(completed[0].completed_at, completed[1].completed_at);
+
+        store.reopen(&completed_ids).expect("reopen");
+        let subtask = store.list().into_iter().find(|task| task.id == subtask);
+        assert_eq!(subtask.unwrap().parent, Some(parent));
+    }
+
+    #[test]
+    fn test_discard() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let path = testdir.path().join(DATABASE_FILENAME);
+        let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+        let mut store = Store::new(tasks, completed, discarded).expect("store");
+
+        let id = store.add(NewTask::new(String::from("mistake"))).unwrap();
+        store.discard(&[id], Some("duplicate")).expect("discard");
+        assert!(store.list().is_empty());
+        assert!(store.completed().unwrap().is_empty());
+
+        let conn = Connection::open(&path).unwrap();
+        let (description, reason): (String, String) = conn
+            .query_row(
+                "SELECT description, reason FROM discarded WHERE id = ?1",
+                params![id.to_string()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(description, "mistake");
+        assert_eq!(reason, "duplicate");
+    }
+
+    #[test]
+    fn test_remove_rolls_back() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let path = testdir.path().join(DATABASE_FILENAME);
+        let (mut tasks, mut completed, _) = open(&path).expect(DATABASE_FILENAME);
+
+        let id = tasks.create(NewTask::new(String::from("a thing"))).unwrap();
+        let result = tasks.remove(&[id], |removed| {
+            let removed = removed
+                .into_iter()
+                .map(CompletedTask::from)
+                .collect::<Vec<_>>();
+            completed.add(&removed)?;
+            Err(Error::from(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "failed",
+            )))
+        });
+        assert!(result.is_err());
+
+        // Neither list should have changed
+        let (tasks, completed, _) = open(&path).expect(DATABASE_FILENAME);
+        assert_eq!(tasks.list().len(), 1);
+        assert!(completed.list_completed().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_remove_adds_tasks() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let path = testdir.path().join(DATABASE_FILENAME);
+        let (mut tasks, mut completed, _) = open(&path).expect(DATABASE_FILENAME);
+
+        let id = tasks.create(NewTask::new(String::from("weekly"))).unwrap();
+        let other = tasks.create(NewTask::new(String::from("other"))).unwrap();
+        let next = Task::from(NewTask::new(String::from("weekly")));
+        let next_id = next.id;
+        tasks
+            .remove(&[id], |removed| {
+                let removed = removed
+                    .into_iter()
+                    .map(CompletedTask::from)
+                    .collect::<Vec<_>>();
+                completed.add(&removed)?;
+                Ok(vec![next])
+            })
+            .expect("remove");
+        let ids: Vec<_> = tasks.list().iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![other, next_id]);
+
+        // Adding a task with an id that is already present fails, which should undo
+        // the removal and the completion
+        let duplicate = tasks.list()[0].clone();
+        let result = tasks.remove(&[next_id], |removed| {
+            let removed = removed
+                .into_iter()
+                .map(CompletedTask::from)
+                .collect::<Vec<_>>();
+            completed.add(&removed)?;
+            Ok(vec![duplicate])
+        });
+        assert!(result.is_err());
+
+        let (tasks, completed, _) = open(&path).expect(DATABASE_FILENAME);
+        let ids: Vec<_> = tasks.list().iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![other, next_id]);
+        assert_eq!(completed.list_completed().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_completed_twice() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let path = testdir.path().join(DATABASE_FILENAME);
+        let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+        let mut store = Store::new(tasks, completed, discarded).expect("store");
+
+        let id = store.add(NewTask::new(String::from("again"))).unwrap();
+        store.complete(&[id]).expect("complete");
+        store.reopen(&[id]).expect("reopen");
+        store.complete(&[id]).expect("complete");
+
+        // Like completed.csv, the completed table accepts the same id more than once
+        let task = store.completed().unwrap().remove(0);
+        let (_, mut completed, _) = open(&path).expect(DATABASE_FILENAME);
+        completed.add(&[task]).expect("add");
+
+        let (tasks, completed, discarded) = open(&path).expect(DATABASE_FILENAME);
+        let mut store = Store::new(tasks, completed, discarded).expect("store");
+        assert_eq!(store.completed().unwrap().len(), 2);
+        assert_eq!(store.reopen(&[id]).expect("reopen"), vec![id]);
+        assert_eq!(store.list().len(), 1);
+        assert!(store.completed().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,50 @@
+//! Tags.
+//!
+//! Tasks are tagged by including `#tag` in their description. Tags are case
+//! insensitive and are stored lowercase.
+
+use std::collections::BTreeSet;
+
+/// Extract the `#tags` from `description`.
+///
+/// A tag starts at the beginning of a word and runs until the first character that
+/// is not alphanumeric, `-`, or `_`. Tags must start with a letter so that things
+/// like issue numbers (#123) are not treated as tags.
+pub fn parse(description: &str) -> BTreeSet<String> {
+    description
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('#'))
+        .filter_map(|word| {
+            let end = word
+                .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+                .unwrap_or_else(|| word.len());
+            let tag = &word[..end];
+            if tag.starts_with(char::is_alphabetic) {
+                Some(tag.to_lowercase())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(description: &str) -> Vec<String> {
+        parse(description).into_iter().collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(tags("no tags").is_empty());
+        assert_eq!(tags("#reading a post"), vec!["reading"]);
+        assert_eq!(tags("read a post #Reading #rust"), vec!["reading", "rust"]);
+        assert_eq!(tags("(#to-do_list), #later."), vec!["later"]);
+        assert_eq!(tags("#émigré"), vec!["émigré"]);
+        assert_eq!(tags("fix #123 ## # #-x"), Vec::<String>::new());
+        assert_eq!(tags("https://example.com/#anchor"), Vec::<String>::new());
+        assert_eq!(tags("#a #a #A"), vec!["a"]);
+    }
+}
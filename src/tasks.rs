@@ -1,59 +1,602 @@
 //! Task handling routes.
 
+use chrono::Local;
+use rocket::http::Status;
 use rocket::request::{FlashMessage, LenientForm};
 use rocket::response::{content, Flash, Redirect};
 use rocket::{Route, State};
 
-use leaf::models::{NewTask, Store};
+use leaf::lists::{self, Lists, DEFAULT_LIST};
+use leaf::models::{self, NewTask, Store, TaskId};
+use leaf::quick_add;
+use leaf::tokens::Scope;
+use leaf::users::Users;
 
 use crate::auth::{self, User, UserOrToken};
-use crate::form::TasksForm;
+use crate::form::{DiscardTaskForm, EditTaskForm, NewListForm, TaskFilter, TaskIdsForm, TasksForm};
 use crate::templates;
 
+pub const COMPLETED_PER_PAGE: usize = 50;
+
+/// The most search results shown from each list.
+pub const SEARCH_RESULTS_PER_LIST: usize = 100;
+
+/// Name of the flash message set after completing tasks. The message holds the
+/// comma separated ids of the tasks that were completed so that they can be reopened.
+const COMPLETED_FLASH: &str = "completed";
+
+#[derive(Responder)]
+enum Saved {
+    Undoable(Flash<Redirect>),
+    Message(Flash<Redirect>),
+    Done(Redirect),
+}
+
 pub fn routes() -> Vec<Route> {
-    routes![index, index_logged_out, form]
+    routes![
+        index,
+        index_logged_out,
+        form,
+        all_lists,
+        create_list,
+        list,
+        list_form,
+        show,
+        edit,
+        update,
+        discard,
+        completed,
+        completed_default,
+        reopen,
+        search
+    ]
 }
 
 #[get("/")]
-fn index(user: User, _msg: Option<FlashMessage>, state: State<Store>) -> content::Html<String> {
-    let store = state.lock().unwrap();
-    let page: templates::Layout<'_, '_, _> = templates::Layout {
+fn index(_user: User) -> Redirect {
+    Redirect::to(list_url(DEFAULT_LIST))
+}
+
+#[get("/", rank = 2)]
+fn index_logged_out() -> Redirect {
+    Redirect::to(uri!(auth::login_page))
+}
+
+#[get("/lists")]
+fn all_lists(
+    user: User,
+    flash: Option<FlashMessage>,
+    users: State<Users>,
+) -> content::Html<String> {
+    let state = user_lists(&users, &user);
+    let names = state.names();
+    let page: templates::Layout<'_, _> = templates::Layout {
+        title: "Lists",
+        body: templates::Lists {
+            lists: &names,
+            flash: flash.as_ref().map(|flash| flash.msg()),
+        },
+        user: Some(&user),
+        lists: &nav_lists(&state),
+        list: None,
+    };
+    content::Html(page.to_string())
+}
+
+#[post("/lists", data = "<form>")]
+fn create_list(
+    user: User,
+    form: LenientForm<NewListForm>,
+    users: State<Users>,
+) -> Result<Redirect, Flash<Redirect>> {
+    let state = user_lists(&users, &user);
+    let name = form.into_inner().name;
+    let name = name.trim();
+    if !lists::is_valid_name(name) {
+        return Err(Flash::error(
+            Redirect::to("/lists"),
+            "List names may only contain lowercase letters, digits, - and _",
+        ));
+    }
+
+    match state.create(name) {
+        Some(Ok(_)) => Ok(Redirect::to(list_url(name))),
+        Some(Err(err)) => {
+            log::error!("{}: {}", err, err.source);
+            Err(Flash::error(
+                Redirect::to("/lists"),
+                "Failed to create list",
+            ))
+        }
+        None => Err(Flash::error(
+            Redirect::to("/lists"),
+            "Multiple lists are not enabled",
+        )),
+    }
+}
+
+#[get("/lists/<list>?<filter..>")]
+fn list(
+    user: User,
+    list: String,
+    filter: LenientForm<TaskFilter>,
+    msg: Option<FlashMessage>,
+    users: State<Users>,
+) -> Result<content::Html<String>, Status> {
+    let state = user_lists(&users, &user);
+    let store = state.get(&list).ok_or(Status::NotFound)?;
+    let (message, undo_ids) = match msg {
+        Some(ref msg) if msg.name() == COMPLETED_FLASH => {
+            let ids = msg.msg().split(',').collect::<Vec<_>>();
+            let message = match ids.len() {
+                1 => String::from("Completed 1 task."),
+                count => format!("Completed {} tasks.", count),
+            };
+            (Some(message), ids)
+        }
+        Some(ref msg) => (Some(msg.msg().to_string()), Vec::new()),
+        None => (None, Vec::new()),
+    };
+    let store = store.lock().unwrap();
+    let tasks = if filter.snoozed {
+        store.snoozed()
+    } else {
+        store.list()
+    };
+    let mut tasks = tasks
+        .into_iter()
+        .filter(|task| filter.matches(task))
+        .collect::<Vec<_>>();
+    templates::sort_tasks(&mut tasks);
+    let page: templates::Layout<'_, _> = templates::Layout {
         title: "Tasks",
         body: templates::Index {
-            tasks: store.list(),
+            list: &list,
+            tasks,
+            filter: &filter,
+            flash: message.as_deref(),
+            undo_ids,
         },
         user: Some(&user),
+        lists: &nav_lists(&state),
+        list: Some(&list),
     };
-    content::Html(page.to_string())
+    Ok(content::Html(page.to_string()))
 }
 
-#[get("/", rank = 2)]
-fn index_logged_out() -> Redirect {
-    Redirect::to(uri!(auth::login_page))
+#[get("/lists/<list>/completed?<page>")]
+fn completed(
+    user: User,
+    list: String,
+    page: Option<usize>,
+    users: State<Users>,
+) -> Result<content::Html<String>, Status> {
+    let state = user_lists(&users, &user);
+    let store = state.get(&list).ok_or(Status::NotFound)?;
+    let page_number = page.unwrap_or(1).max(1);
+    let completed = {
+        let store = store.lock().unwrap();
+        store.completed().map_err(|err| {
+            log::error!("unable to read completed tasks: {}", err);
+            Status::InternalServerError
+        })?
+    };
+    let tasks = completed
+        .iter()
+        .skip((page_number - 1) * COMPLETED_PER_PAGE)
+        .take(COMPLETED_PER_PAGE)
+        .collect::<Vec<_>>();
+    let page: templates::Layout<'_, _> = templates::Layout {
+        title: "Completed",
+        body: templates::Completed {
+            list: &list,
+            days: templates::group_by_day(&tasks),
+            page: page_number,
+            more: completed.len() > page_number * COMPLETED_PER_PAGE,
+        },
+        user: Some(&user),
+        lists: &nav_lists(&state),
+        list: Some(&list),
+    };
+    Ok(content::Html(page.to_string()))
 }
 
-#[post("/tasks", data = "<form>")]
+/// Add tasks to the default list, or the list named by `list`.
+///
+/// This route is also used by API clients, such as the iOS shortcut, so it
+/// remains at its original location. API tokens need the `tasks:add`,
+/// `tasks:complete`, or `tasks:edit` scope for the changes being made.
+#[post("/tasks?<list>", data = "<form>")]
 fn form(
-    _auth: UserOrToken,
+    auth: UserOrToken,
+    list: Option<String>,
     form: LenientForm<TasksForm>,
-    state: State<Store>,
-) -> Result<Redirect, Flash<Redirect>> {
+    users: State<Users>,
+) -> Result<Result<Saved, Flash<Redirect>>, Status> {
     let form = form.into_inner();
-    let mut store = state.lock().unwrap();
+    let username = match auth {
+        UserOrToken::User(ref user) => user.username(),
+        UserOrToken::Token(ref token) => {
+            if (form.new_task.is_some() && !token.allows(Scope::TasksAdd))
+                || (!form.completed_ids.is_empty() && !token.allows(Scope::TasksComplete))
+                || ((form.move_task.is_some() || form.snooze.is_some())
+                    && !token.allows(Scope::TasksEdit))
+            {
+                return Err(Status::Forbidden);
+            }
+            token.username()
+        }
+    };
+    // NOTE(unwrap): The User and Token guards only succeed for existing accounts
+    let state = &users.get(username).unwrap().lists;
+    let list = list.as_deref().unwrap_or(DEFAULT_LIST);
+    Ok(save(state, list, form))
+}
+
+#[post("/lists/<list>/tasks", data = "<form>")]
+fn list_form(
+    user: User,
+    list: String,
+    form: LenientForm<TasksForm>,
+    users: State<Users>,
+) -> Result<Saved, Flash<Redirect>> {
+    let state = user_lists(&users, &user);
+    save(state, &list, form.into_inner())
+}
+
+/// Add the new task, if any, to `list` or the list named in its description, then
+/// move, snooze, and complete tasks in `list`.
+fn save(lists: &Lists, list: &str, form: TasksForm) -> Result<Saved, Flash<Redirect>> {
+    let list_page = || Redirect::to(list_url(list));
+    let store = find_list(lists, list)?;
+
+    // The move and snooze buttons submit the whole list form, so a new task and
+    // checked tasks are only saved by the Save button. Say so rather than silently
+    // dropping them.
+    let unsaved = match (form.new_task.is_some(), !form.completed_ids.is_empty()) {
+        (true, true) => Some("The new task and checked tasks were not saved, use Save for those."),
+        (true, false) => Some("The new task was not saved, use Save to add it."),
+        (false, true) => Some("Checked tasks were not completed, use Save to complete them."),
+        (false, false) => None,
+    };
+    let finish = |message: Option<String>| match (message, unsaved) {
+        (Some(message), Some(unsaved)) => Saved::Message(Flash::warning(
+            list_page(),
+            format!("{} {}", message, unsaved),
+        )),
+        (None, Some(unsaved)) => Saved::Message(Flash::warning(list_page(), unsaved)),
+        (Some(message), None) => Saved::Message(Flash::success(list_page(), message)),
+        (None, None) => Saved::Done(list_page()),
+    };
+
+    // Move a task if one of the move buttons was used
+    if let Some((id, position)) = form.move_task {
+        store
+            .lock()
+            .unwrap()
+            .move_task(id, position)
+            .map_err(|_err| Flash::error(list_page(), "Failed to move task"))?;
+        return Ok(finish(None));
+    }
+
+    // Snooze or show a task if one of the snooze buttons was used
+    if let Some((id, until)) = form.snooze {
+        store
+            .lock()
+            .unwrap()
+            .edit(id, |task| task.hidden_until = until)
+            .map_err(|_err| Flash::error(list_page(), "Failed to snooze task"))?;
+        let message =
+            until.map(|until| format!("Snoozed 1 task until {}.", until.format("%A %-d %B")));
+        return Ok(finish(message));
+    }
 
     // Create new task if present
     if let Some(description) = form.new_task {
-        let task = NewTask { description };
+        let task = quick_add::parse(&description, Local::today().naive_local());
+        if task.description.is_empty() {
+            return Err(Flash::error(list_page(), "Description can't be blank"));
+        }
+        let store = match task.list {
+            Some(ref name) => lists
+                .get(name)
+                .ok_or_else(|| Flash::error(list_page(), format!("No list named {}", name)))?,
+            None => store.clone(),
+        };
+        let mut store = store.lock().unwrap();
+        if let Some(parent) = form.parent {
+            if !store.can_nest(None, parent) {
+                return Err(Flash::error(
+                    list_page(),
+                    "Can't add a subtask to that task",
+                ));
+            }
+        }
+        let due = form.due.or(task.due);
+        let task = NewTask {
+            due,
+            parent: form.parent,
+            ..NewTask::from(task)
+        };
         log::debug!("create_task: {:?}", task);
         store
             .add(task)
-            .map_err(|_err| Flash::error(Redirect::to("/"), "Failed to add new task"))?;
+            .map_err(|_err| Flash::error(list_page(), "Failed to add new task"))?;
     }
 
-    // Complete any checked tasks
-    store
+    // Complete any checked tasks, along with their subtasks
+    let completed_ids = store
+        .lock()
+        .unwrap()
         .complete(&form.completed_ids)
-        .map_err(|_err| Flash::error(Redirect::to("/"), "Failed to complete tasks"))?;
+        .map_err(|_err| Flash::error(list_page(), "Failed to complete tasks"))?;
 
-    Ok(Redirect::to("/"))
+    if completed_ids.is_empty() {
+        Ok(Saved::Done(list_page()))
+    } else {
+        let ids = completed_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
+        Ok(Saved::Undoable(Flash::new(
+            list_page(),
+            COMPLETED_FLASH,
+            ids.join(","),
+        )))
+    }
+}
+
+/// The Completed page from before there were multiple lists.
+#[get("/completed?<page>")]
+fn completed_default(_user: User, page: Option<usize>) -> Redirect {
+    let url = format!("{}/completed", list_url(DEFAULT_LIST));
+    match page {
+        Some(page) => Redirect::to(format!("{}?page={}", url, page)),
+        None => Redirect::to(url),
+    }
+}
+
+#[get("/lists/<list>/tasks/<id>")]
+fn show(
+    user: User,
+    list: String,
+    id: String,
+    users: State<Users>,
+) -> Result<content::Html<String>, Status> {
+    let state = user_lists(&users, &user);
+    let store = state.get(&list).ok_or(Status::NotFound)?;
+    let id = id.parse::<TaskId>().map_err(|_| Status::NotFound)?;
+    let store = store.lock().unwrap();
+    let task = store
+        .all()
+        .iter()
+        .find(|task| task.id == id)
+        .ok_or(Status::NotFound)?;
+    let parent = task
+        .parent
+        .and_then(|parent| store.all().iter().find(|task| task.id == parent));
+    let subtasks = store
+        .all()
+        .iter()
+        .filter(|subtask| subtask.parent == Some(id))
+        .collect();
+    let page: templates::Layout<'_, _> = templates::Layout {
+        title: "Task",
+        body: templates::Show {
+            list: &list,
+            task,
+            parent,
+            subtasks,
+            today: Local::today().naive_local(),
+        },
+        user: Some(&user),
+        lists: &nav_lists(&state),
+        list: Some(&list),
+    };
+    Ok(content::Html(page.to_string()))
+}
+
+#[get("/lists/<list>/tasks/<id>/edit")]
+fn edit(
+    user: User,
+    list: String,
+    id: String,
+    flash: Option<FlashMessage>,
+    users: State<Users>,
+) -> Result<content::Html<String>, Status> {
+    let state = user_lists(&users, &user);
+    let store = state.get(&list).ok_or(Status::NotFound)?;
+    let id = id.parse::<TaskId>().map_err(|_| Status::NotFound)?;
+    let store = store.lock().unwrap();
+    let task = store
+        .all()
+        .iter()
+        .find(|task| task.id == id)
+        .ok_or(Status::NotFound)?;
+    let parents = store
+        .all()
+        .iter()
+        .filter(|parent| store.can_nest(Some(id), parent.id))
+        .collect();
+    let page: templates::Layout<'_, _> = templates::Layout {
+        title: "Edit Task",
+        body: templates::Edit {
+            list: &list,
+            task,
+            parents,
+            flash: flash.as_ref().map(|flash| flash.msg()),
+        },
+        user: Some(&user),
+        lists: &nav_lists(&state),
+        list: Some(&list),
+    };
+    Ok(content::Html(page.to_string()))
+}
+
+#[post("/lists/<list>/tasks/<id>", data = "<form>")]
+fn update(
+    user: User,
+    list: String,
+    id: String,
+    form: LenientForm<EditTaskForm>,
+    users: State<Users>,
+) -> Result<Redirect, Flash<Redirect>> {
+    let state = user_lists(&users, &user);
+    let store = find_list(&state, &list)?;
+    let edit_page = Redirect::to(format!("{}/tasks/{}/edit", list_url(&list), id));
+    let id = id
+        .parse::<TaskId>()
+        .map_err(|_| Flash::error(Redirect::to(list_url(&list)), "Task not found"))?;
+    let form = form.into_inner();
+    if form.description.trim().is_empty() {
+        return Err(Flash::error(edit_page, "Description can't be blank"));
+    }
+    let recurrence = match form.recurrence.as_deref().map(str::trim) {
+        Some("") => Some(None),
+        Some(rule) => match rule.parse() {
+            Ok(recurrence) => Some(Some(recurrence)),
+            Err(_) => {
+                return Err(Flash::error(
+                    edit_page,
+                    "Repeat must be like daily, weekly, monthly, or every 3 days",
+                ))
+            }
+        },
+        None => None,
+    };
+
+    let mut store = store.lock().unwrap();
+    let parent = form.parent.as_ref().map(|parent| parent.0);
+    if let Some(Some(parent)) = parent {
+        if !store.can_nest(Some(id), parent) {
+            return Err(Flash::error(
+                edit_page,
+                "Can't make this a subtask of that task",
+            ));
+        }
+    }
+    store
+        .edit(id, |task| {
+            task.description = form.description;
+            task.due = form.due.0;
+            if let Some(priority) = form.priority {
+                task.priority = priority.0;
+            }
+            if let Some(notes) = form.notes {
+                task.notes = models::clean_notes(&notes);
+            }
+            if let Some(recurrence) = recurrence {
+                task.recurrence = recurrence;
+            }
+            if let Some(hidden_until) = form.hidden_until {
+                task.hidden_until = hidden_until.0;
+            }
+            if let Some(parent) = parent {
+                task.parent = parent;
+            }
+        })
+        .map_err(|_err| Flash::error(edit_page, "Failed to save task"))?;
+
+    Ok(Redirect::to(task_url(&list, id)))
+}
+
+#[post("/lists/<list>/tasks/<id>/discard", data = "<form>")]
+fn discard(
+    user: User,
+    list: String,
+    id: String,
+    form: LenientForm<DiscardTaskForm>,
+    users: State<Users>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let state = user_lists(&users, &user);
+    let store = find_list(&state, &list)?;
+    let list_page = || Redirect::to(list_url(&list));
+    let id = id
+        .parse::<TaskId>()
+        .map_err(|_| Flash::error(list_page(), "Task not found"))?;
+    let reason = form
+        .into_inner()
+        .reason
+        .filter(|reason| !reason.trim().is_empty());
+
+    let mut store = store.lock().unwrap();
+    if !store.all().iter().any(|task| task.id == id) {
+        return Err(Flash::error(list_page(), "Task not found"));
+    }
+    store
+        .discard(&[id], reason.as_deref())
+        .map_err(|_err| Flash::error(list_page(), "Failed to discard task"))?;
+
+    Ok(Flash::success(list_page(), "Discarded 1 task."))
+}
+
+#[post("/lists/<list>/completed/reopen", data = "<form>")]
+fn reopen(
+    user: User,
+    list: String,
+    form: LenientForm<TaskIdsForm>,
+    users: State<Users>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let state = user_lists(&users, &user);
+    let store = find_list(&state, &list)?;
+    let list_page = || Redirect::to(list_url(&list));
+    let mut store = store.lock().unwrap();
+    let reopened_ids = store
+        .reopen(&form.ids)
+        .map_err(|_err| Flash::error(list_page(), "Failed to reopen tasks"))?;
+
+    let message = match reopened_ids.len() {
+        0 => return Err(Flash::error(list_page(), "No completed tasks to reopen")),
+        1 => String::from("Reopened 1 task."),
+        count => format!("Reopened {} tasks.", count),
+    };
+    Ok(Flash::success(list_page(), message))
+}
+
+#[get("/search?<q>")]
+fn search(user: User, q: Option<String>, users: State<Users>) -> content::Html<String> {
+    let state = user_lists(&users, &user);
+    let query = q.as_deref().map(str::trim).unwrap_or("");
+    let results = state.search(query, SEARCH_RESULTS_PER_LIST);
+    let page: templates::Layout<'_, _> = templates::Layout {
+        title: "Search",
+        body: templates::Search {
+            query,
+            results: &results,
+            show_lists: state.is_enabled(),
+        },
+        user: Some(&user),
+        lists: &nav_lists(&state),
+        list: None,
+    };
+    content::Html(page.to_string())
+}
+
+fn list_url(list: &str) -> String {
+    format!("/lists/{}", list)
+}
+
+fn task_url(list: &str, id: TaskId) -> String {
+    format!("{}/tasks/{}", list_url(list), id)
+}
+
+fn find_list(lists: &Lists, name: &str) -> Result<Store, Flash<Redirect>> {
+    lists
+        .get(name)
+        .ok_or_else(|| Flash::error(Redirect::to("/lists"), "List not found"))
+}
+
+/// The lists of the signed in user.
+pub(crate) fn user_lists<'a>(users: &'a Users, user: &User) -> &'a Lists {
+    // NOTE(unwrap): The User guard only succeeds for existing accounts
+    &users.get(user.username()).unwrap().lists
+}
+
+/// The lists to show in the list switcher, if multiple lists are enabled.
+pub(crate) fn nav_lists(lists: &Lists) -> Vec<String> {
+    if lists.is_enabled() {
+        lists.names()
+    } else {
+        Vec::new()
+    }
 }
@@ -1,32 +1,85 @@
 use std::fmt;
 
-use leaf::models;
+use chrono::{Datelike, Duration, Local, NaiveDate, Utc};
+use leaf::models::{self, Priority, TaskId, Timestamp};
+use leaf::search::Document;
+use leaf::tokens::{ApiToken, Scope};
 use markup::Render;
 use regex::Regex;
+use rocket::http::uri::Uri;
 
 use crate::auth::User;
+use crate::feeds::Entry;
+use crate::form::{DueFilter, TaskFilter};
+
+/// Tasks older than this many days are highlighted as stale.
+const STALE_AGE_DAYS: i64 = 28;
 
 struct AutoLink<'a>(&'a str);
 
+lazy_static! {
+    // Based on http://www.urlregex.com/ (Python version), with the characters
+    // from its `$-_` range listed so `<`, `>`, `"`, and `'` end a URL
+    static ref URL_RE: Regex = Regex::new(
+        "http[s]?://(?:[a-zA-Z]|[0-9]|[$\\-_@.&+/:;=?#~]|[!*(),]|(?:%[0-9a-fA-F][0-9a-fA-F]))+"
+    )
+    .unwrap();
+}
+
+/// Tasks to move a task before or after, see `moves`.
+#[derive(Debug, PartialEq)]
+pub struct Moves {
+    top: Option<TaskId>,
+    up: Option<TaskId>,
+    down: Option<TaskId>,
+}
+
+/// A task in the list along with its subtasks, see `rows`.
+pub struct Row<'a> {
+    task: &'a models::Task,
+    moves: Moves,
+    subtasks: Vec<Row<'a>>,
+}
+
 markup::define! {
-    Layout<'title, 'user, Body: markup::Render>(body: Body, title: &'title str, user: Option<&'user User>) {
+    Layout<'a, Body: markup::Render>(body: Body, title: &'a str, user: Option<&'a User>, lists: &'a [String], list: Option<&'a str>) {
         {markup::doctype()}
         html[lang="en"] {
             head {
                 meta[charset="utf-8"];
                 meta[name="viewport", content="width=device-width, initial-scale=1"];
                 title { { title } " – Leaf" }
-                link[rel="stylesheet", href="app.css", type="text/css", charset="utf-8"];
+                link[rel="stylesheet", href="/app.css", type="text/css", charset="utf-8"];
                 link[rel="icon", href=r#"data:image/svg+xml,<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100"><text y=".9em" font-size="90">🍃</text></svg>"#];
             }
             body {
                 header.center {
+                    @if !lists.is_empty() {
+                        nav.lists {
+                            @for name in lists.iter() {
+                                a[href=format!("/lists/{}", name), class=if *list == Some(name.as_str()) { Some("current") } else { None }] { {name} }
+                                " · "
+                            }
+                            a[href="/lists"] { "All lists" }
+                        }
+                    }
                     h1 { { title } }
                 }
                 main {
                     { body }
                 }
                 footer.center {
+                    @if let Some(list) = *(list) {
+                        nav {
+                            a[href=format!("/lists/{}", list)] {"Tasks"}
+                            " · "
+                            a[href=format!("/lists/{}/completed", list)] {"Completed"}
+                            " · "
+                            a[href="/search"] {"Search"}
+                            " · "
+                            a[href="/tokens"] {"API Tokens"}
+                        }
+                    }
                     div.copyright {
                         a[href="https://github.com/wezm/leaf"] {"Leaf Tasks"}
                         @if user.is_some() {
@@ -40,71 +93,605 @@ markup::define! {
             }
         }
     }
-    Index<'tasks>(tasks: &'tasks [models::Task]) {
-        form[action="/tasks", method="POST"] {
+    Index<'a>(list: &'a str, tasks: Vec<&'a models::Task>, filter: &'a TaskFilter, flash: Option<&'a str>, undo_ids: Vec<&'a str>) {
+        @if let Some(ref message) = *(flash) {
+            .flash.center {
+                { message }
+                @if !undo_ids.is_empty() {
+                    " "
+                    form.undo[action=format!("/lists/{}/completed/reopen", list), method="POST"] {
+                        @for id in undo_ids.iter() {
+                            input[type="hidden", name="id", value=id];
+                        }
+                        input[type="submit", name="submit", value="Undo"];
+                    }
+                }
+            }
+        }
+        nav.filters {
+            a[href=format!("/lists/{}", list), class=if filter.is_empty() { Some("current") } else { None }] { "All" }
+            " · "
+            a[href=format!("/lists/{}?due=today", list), class=if filter.due == Some(DueFilter::Today) { Some("current") } else { None }] { "Due today" }
+            " · "
+            a[href=format!("/lists/{}?snoozed=true", list), class=if filter.snoozed { Some("current") } else { None }] { "Snoozed" }
+            @if let Some(tag) = filter.tag() {
+                " · "
+                a.current[href=tag_url(list, tag)] { "#" {tag} }
+            }
+        }
+        form[action=format!("/lists/{}/tasks", list), method="POST"] {
             ul."task-list" {
                 li."new-task" {
                     span.ornament {{markup::raw("➕&#xFE0E; ")}}
                     input[type="text", name="description", placeholder="New task", autofocus?=true];
+                    input[type="date", name="due", title="Due date (optional)"];
+                    // The first submit button is the one used when Enter is pressed
+                    input[type="submit", name="submit", value="Add"];
                 }
-                @for task in *(tasks) {
-                    {Task { id: task.id.to_string(), description: &task.description }}
+                @for row in rows(tasks).iter() {
+                    {Task { list, task: row.task, today: Local::today().naive_local(), moves: &row.moves, subtasks: &row.subtasks }}
                 }
             }
+            @if tasks.is_empty() && !filter.is_empty() {
+                p.center { "No matching tasks." }
+            }
 
             div.actions {
                 input[type="submit", name="submit", value="Save"];
             }
+            @if tasks.len() > 1 {
+                p."sort-note".center { "Tasks are sorted by priority, then due date. ⤒ ↑ ↓ only reorder tasks that share both." }
+            }
         }
     }
-    Task<'a>(id: String, description: &'a str) {
-        li {
+    Task<'a, 'b>(list: &'a str, task: &'a models::Task, today: NaiveDate, moves: &'b Moves, subtasks: &'b [Row<'a>]) {
+        li[class=task_class(task, *today)] {
             label {
-                input[type="checkbox", name=format!("complete_{}", id), value=id];
+                input[type="checkbox", name=format!("complete_{}", task.id), value=task.id.to_string()];
+                " "
+                {AutoLink(&task.description)}
+            }
+            " "
+            @for tag in task.tags().iter() {
+                a.tag[href=tag_url(list, tag)] { "#" {tag} }
+                " "
+            }
+            @if let Some(label) = priority_label(task.priority) {
+                span.priority { {label} }
+                " "
+            }
+            @if let Some(due) = task.due {
+                span.due { {due_label(due, *today)} }
+                " "
+            }
+            @if let Some(recurrence) = task.recurrence {
+                span.recurrence[title=format!("Repeats {}", recurrence)] { "↻" }
+                " "
+            }
+            {Age { time: task.created_at }}
+            " "
+            span.move {
+                button[type="submit", name="move", value=moves.top.map(|other| format!("{}:before:{}", task.id, other)), title="Move to top, among tasks with the same priority and due date", disabled?=moves.top.is_none()] { "⤒" }
+                button[type="submit", name="move", value=moves.up.map(|other| format!("{}:before:{}", task.id, other)), title="Move up, among tasks with the same priority and due date", disabled?=moves.up.is_none()] { "↑" }
+                button[type="submit", name="move", value=moves.down.map(|other| format!("{}:after:{}", task.id, other)), title="Move down, among tasks with the same priority and due date", disabled?=moves.down.is_none()] { "↓" }
+            }
+            " "
+            span.snooze {
+                @if let Some(until) = task.hidden_until.filter(|_| task.is_hidden(*today)) {
+                    span.until[title=until.format("%-d %B %Y").to_string()] { "Until " {until.format("%a %-d %b").to_string()} }
+                    " "
+                    button[type="submit", name="snooze", value=format!("{}:", task.id), title="Show this task in the list again"] { "Show now" }
+                } else {
+                    button[type="submit", name="snooze", value=format!("{}:{}", task.id, *today + Duration::days(1)), title="Hide until tomorrow"] { "Tomorrow" }
+                    button[type="submit", name="snooze", value=format!("{}:{}", task.id, next_week(*today)), title="Hide until next Monday"] { "Next week" }
+                }
+            }
+            " "
+            @if task.notes.is_some() {
+                a.notes[href=format!("/lists/{}/tasks/{}", list, task.id), title="Notes"] { "Notes" }
+                " "
+            }
+            a.edit[href=format!("/lists/{}/tasks/{}/edit", list, task.id), title="Edit"] { "Edit" }
+            @if !subtasks.is_empty() {
+                ul.subtasks {
+                    @for row in subtasks.iter() {
+                        {Task { list, task: row.task, today: *today, moves: &row.moves, subtasks: &row.subtasks }}
+                    }
+                }
+            }
+        }
+    }
+    Age(time: Timestamp) {
+        time[class=age_class(*time, Utc::now()), datetime=time.to_rfc3339(), title=time.format("%-d %B %Y").to_string()] {
+            {relative_age(*time, Utc::now())}
+        }
+    }
+    Show<'a>(list: &'a str, task: &'a models::Task, parent: Option<&'a models::Task>, subtasks: Vec<&'a models::Task>, today: NaiveDate) {
+        .task {
+            @if let Some(parent) = *(parent) {
+                p.parent {
+                    "Subtask of "
+                    a[href=format!("/lists/{}/tasks/{}", list, parent.id)] { {&parent.description} }
+                }
+            }
+            h2 { {AutoLink(&task.description)} }
+            p.details {
+                @if let Some(label) = priority_label(task.priority) {
+                    span.priority { {label} }
+                    " "
+                }
+                @if let Some(due) = task.due {
+                    span.due { {due_label(due, *today)} }
+                    " "
+                }
+                @if let Some(recurrence) = task.recurrence {
+                    span.recurrence { "Repeats " {recurrence.to_string()} }
+                    " "
+                }
+                @if let Some(until) = task.hidden_until.filter(|_| task.is_hidden(*today)) {
+                    span.until { "Snoozed until " {until.format("%-d %B %Y").to_string()} }
+                    " "
+                }
+                @for tag in task.tags().iter() {
+                    a.tag[href=tag_url(list, tag)] { "#" {tag} }
+                    " "
+                }
+                {Age { time: task.created_at }}
+            }
+            @if let Some(ref notes) = task.notes {
+                .notes { {AutoLink(notes)} }
+            } else {
+                p.notes.empty { "No notes." }
+            }
+            @if parent.is_none() {
+                h3 { "Subtasks" }
+                @if !subtasks.is_empty() {
+                    ul."task-list".subtasks {
+                        @for subtask in subtasks.iter() {
+                            li { a[href=format!("/lists/{}/tasks/{}", list, subtask.id)] { {&subtask.description} } }
+                        }
+                    }
+                }
+                form."new-subtask"[action=format!("/lists/{}/tasks", list), method="POST"] {
+                    input[type="hidden", name="parent", value=task.id.to_string()];
+                    input[type="text", name="description", placeholder="New subtask", required?=true];
+                    input[type="submit", name="submit", value="Add"];
+                }
+            }
+            div.actions {
+                a[href=format!("/lists/{}", list)] { "Back" }
                 " "
-                {AutoLink(description)}
+                a[href=format!("/lists/{}/tasks/{}/edit", list, task.id)] { "Edit" }
             }
         }
     }
-    Login<'a>(flash: Option<&'a str>) {
+    Edit<'a>(list: &'a str, task: &'a models::Task, parents: Vec<&'a models::Task>, flash: Option<&'a str>) {
+        form.edit.center[action=format!("/lists/{}/tasks/{}", list, task.id), method="POST"] {
+            @if let Some(ref message) = *(flash) {
+                .flash.center { { message } }
+            }
+            label[for="description"] { "Description" }
+            input#description[type="text", name="description", value=&task.description, required?=true, autofocus?=true];
+            label[for="due"] { "Due date" }
+            input#due[type="date", name="due", value=task.due.map(|due| due.to_string())];
+            label[for="priority"] { "Priority" }
+            select#priority[name="priority"] {
+                @for priority in Priority::ALL.iter() {
+                    option[value=priority.as_str(), selected?=*priority == task.priority] { {priority_name(*priority)} }
+                }
+            }
+            label[for="recurrence"] { "Repeats" }
+            input#recurrence[type="text", name="recurrence", value=task.recurrence.map(|recurrence| recurrence.to_string()), placeholder="e.g. weekly, every 3 days"];
+            label[for="hidden_until"] { "Snoozed until" }
+            input#hidden_until[type="date", name="hidden_until", value=task.hidden_until.map(|until| until.to_string()), title="Hide the task from the list until this date (optional)"];
+            @if !parents.is_empty() {
+                label[for="parent"] { "Subtask of" }
+                select#parent[name="parent"] {
+                    option[value="", selected?=task.parent.is_none()] { "None" }
+                    @for parent in parents.iter() {
+                        option[value=parent.id.to_string(), selected?=task.parent == Some(parent.id)] { {&parent.description} }
+                    }
+                }
+            }
+            label[for="notes"] { "Notes" }
+            textarea#notes[name="notes", rows="8"] { {task.notes.as_deref().unwrap_or("")} }
+
+            div.actions {
+                a[href=format!("/lists/{}/tasks/{}", list, task.id)] { "Cancel" }
+                " "
+                input[type="submit", name="submit", value="Save"];
+            }
+        }
+        form.discard[action=format!("/lists/{}/tasks/{}/discard", list, task.id), method="POST"] {
+            label[for="reason"] { "Discard this task without completing it" }
+            input#reason[type="text", name="reason", placeholder="Reason (optional)"];
+            div.actions {
+                input[type="submit", name="submit", value="Discard"];
+            }
+        }
+    }
+    Completed<'a>(list: &'a str, days: Vec<(NaiveDate, Vec<&'a models::CompletedTask>)>, page: usize, more: bool) {
+        @if days.is_empty() {
+            p.center { "No completed tasks." }
+        }
+        @for (day, tasks) in days.iter() {
+            h2.day { {day.format("%A, %-d %B %Y").to_string()} }
+            ul."task-list".completed {
+                @for task in tasks.iter() {
+                    li {
+                        {AutoLink(&task.description)}
+                        " "
+                        form.reopen[action=format!("/lists/{}/completed/reopen", list), method="POST"] {
+                            input[type="hidden", name="id", value=task.id.to_string()];
+                            input[type="submit", name="submit", value="Reopen"];
+                        }
+                    }
+                }
+            }
+        }
+        div.pagination {
+            @if *page > 1 {
+                a.newer[href=format!("/lists/{}/completed?page={}", list, page - 1)] { "← Newer" }
+            }
+            @if *(more) {
+                a.older[href=format!("/lists/{}/completed?page={}", list, page + 1)] { "Older →" }
+            }
+        }
+    }
+    Search<'a>(query: &'a str, results: &'a [(String, Vec<Document>)], show_lists: bool) {
+        form.search.center[action="/search", method="GET"] {
+            input[type="search", name="q", value=query, placeholder="Search tasks and completed tasks", autofocus?=true];
+            input[type="submit", value="Search"];
+        }
+        @if !query.is_empty() && results.is_empty() {
+            p.center { "No matching tasks." }
+        }
+        @for (list, documents) in results.iter() {
+            @if *(show_lists) {
+                h2.list { a[href=format!("/lists/{}", list)] { {list} } }
+            }
+            ul."task-list".results {
+                @for document in documents.iter() {
+                    li {
+                        {AutoLink(&document.description)}
+                        " "
+                        @if let Some(completed_at) = document.completed_at {
+                            span.completed[title=completed_at.to_rfc3339()] { "Completed " {completed_at.format("%-d %B %Y").to_string()} }
+                        } else {
+                            a.view[href=format!("/lists/{}/tasks/{}", list, document.id)] { "View" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Lists<'a>(lists: &'a [String], flash: Option<&'a str>) {
+        @if let Some(ref message) = *(flash) {
+            .flash.center { { message } }
+        }
+        ul."task-list".lists {
+            @for name in lists.iter() {
+                li { a[href=format!("/lists/{}", name)] { {name} } }
+            }
+        }
+        form."new-list".center[action="/lists", method="POST"] {
+            label[for="name"] { "New list" }
+            input#name[type="text", name="name", placeholder="Name", pattern="[a-z0-9_-]+", title="Lowercase letters, digits, - and _", required?=true];
+            div.actions {
+                input[type="submit", name="submit", value="Create"];
+            }
+        }
+    }
+    Tokens<'a>(tokens: &'a [ApiToken], created: Option<(&'a str, &'a str)>, flash: Option<&'a str>, scopes: &'a [Scope]) {
+        @if let Some(ref message) = *(flash) {
+            .flash.center { { message } }
+        }
+        @if let Some((name, secret)) = *(created) {
+            .flash.center.secret {
+                "Created " {name} ". Copy the token now, it won't be shown again: "
+                code { {secret} }
+            }
+        }
+        @if tokens.is_empty() {
+            p.center { "No API tokens." }
+        } else {
+            table.tokens {
+                thead {
+                    tr { th { "Name" } th { "Scopes" } th { "Created" } th { "Last used" } th {} }
+                }
+                tbody {
+                    @for token in tokens.iter() {
+                        tr {
+                            td { {&token.name} }
+                            td { {token.scopes.to_string()} }
+                            td { {token.created_at.format("%Y-%m-%d").to_string()} }
+                            td {
+                                @if let Some(last_used_at) = token.last_used_at {
+                                    {last_used_at.format("%Y-%m-%d %H:%M").to_string()}
+                                } else {
+                                    "Never"
+                                }
+                            }
+                            td {
+                                form.revoke[action="/tokens/revoke", method="POST"] {
+                                    input[type="hidden", name="name", value=&token.name];
+                                    input[type="submit", name="submit", value="Revoke"];
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        form."new-token".center[action="/tokens", method="POST"] {
+            label[for="name"] { "New token" }
+            input#name[type="text", name="name", placeholder="Name, e.g. phone", required?=true];
+            fieldset {
+                @for scope in scopes.iter() {
+                    label {
+                        input[type="checkbox", name="scope", value=scope.as_str()];
+                        " " {scope.as_str()}
+                    }
+                }
+            }
+            div.actions {
+                input[type="submit", name="submit", value="Create"];
+            }
+        }
+    }
+    Login<'a>(flash: Option<&'a str>, multi_user: bool) {
         form.login.center[action="/login", method="POST"] {
             @if let Some(ref message) = *(flash) {
                 .flash.center { { message } }
             }
+            @if *(multi_user) {
+                label[for="username"] { "Username" }
+                input#username[type="text", name="username", autocapitalize="none", required?=true];
+            }
             label[for="password"] { "Password" }
             input#password[type="password", name="password", required?=true];
 
             input[type="submit", name="submit", value="Sign In"];
         }
     }
+    Link<'a>(url: &'a str) {
+        a[href=url, target="_blank"] { {url} }
+    }
+    Feed<'a>(id: &'a str, title: &'a str, self_url: &'a str, author: &'a str, updated: Timestamp, entries: &'a [Entry<'a>]) {
+        {markup::raw(r#"<?xml version="1.0" encoding="utf-8"?>"#)}
+        feed[xmlns="http://www.w3.org/2005/Atom"] {
+            id { {id} }
+            title { {title} }
+            link[rel="self", href=self_url] {}
+            updated { {updated.to_rfc3339()} }
+            author { name { {author} } }
+            @for entry in entries.iter() {
+                entry {
+                    id { {&entry.id} }
+                    title { {entry.title} }
+                    // Atom allows only one alternate link, the rest are related
+                    @for (i, url) in entry.links.iter().enumerate() {
+                        link[rel=if i == 0 { "alternate" } else { "related" }, href=url] {}
+                    }
+                    @for tag in entry.tags.iter() {
+                        category[term=tag] {}
+                    }
+                    published { {entry.published.to_rfc3339()} }
+                    updated { {entry.updated.to_rfc3339()} }
+                    content[type="text"] { {entry.content} }
+                }
+            }
+        }
+    }
 }
 
-impl<'a> Render for AutoLink<'a> {
-    fn render(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // Source http://www.urlregex.com/ (Python version)
-        lazy_static! {
-            static ref RE: Regex = Regex::new(
-                "http[s]?://(?:[a-zA-Z]|[0-9]|[$-_@.&+]|[!*(),]|(?:%[0-9a-fA-F][0-9a-fA-F]))+"
-            )
-            .unwrap();
+/// The distinct URLs in `text` that `AutoLink` would link, in order.
+pub fn urls(text: &str) -> Vec<&str> {
+    let mut urls = Vec::new();
+    for url_match in URL_RE.find_iter(text) {
+        if !urls.contains(&url_match.as_str()) {
+            urls.push(url_match.as_str());
         }
+    }
+    urls
+}
+
+/// How long ago `time` was relative to `now`, e.g. "3 days ago".
+pub fn relative_age(time: Timestamp, now: Timestamp) -> String {
+    let age = now.signed_duration_since(time);
+    let (count, unit) = match age.num_days() {
+        _ if age.num_minutes() < 1 => return String::from("just now"),
+        _ if age.num_hours() < 1 => (age.num_minutes(), "minute"),
+        0 => (age.num_hours(), "hour"),
+        days @ 1..=13 => (days, "day"),
+        days @ 14..=59 => (days / 7, "week"),
+        days @ 60..=364 => (days / 30, "month"),
+        days => (days / 365, "year"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} {}{} ago", count, unit, plural)
+}
+
+fn tag_url(list: &str, tag: &str) -> String {
+    format!("/lists/{}?tag={}", list, Uri::percent_encode(tag))
+}
+
+/// Sort tasks by priority, highest first, then those with a due date first, soonest
+/// first. The order of tasks is otherwise preserved.
+pub fn sort_tasks(tasks: &mut [&models::Task]) {
+    tasks.sort_by_key(|task| sort_key(task));
+}
+
+fn sort_key(task: &models::Task) -> impl Ord {
+    // Tasks without a priority sort with normal priority tasks
+    let priority = match task.priority {
+        Priority::High => 0,
+        Priority::Normal | Priority::None => 1,
+        Priority::Low => 2,
+    };
+    (priority, task.due.is_none(), task.due)
+}
+
+/// The tasks that the task at `index` in the sorted `tasks` can be moved before or
+/// after. Tasks only move among neighbours with the same sort key, as moving
+/// past others would not change the order they are shown in.
+fn moves(tasks: &[&models::Task], index: usize) -> Moves {
+    let key = sort_key(tasks[index]);
+    let same_key = |other: &&&models::Task| sort_key(other) == key;
+    let first = tasks[..index]
+        .iter()
+        .rev()
+        .take_while(same_key)
+        .last()
+        .map(|task| task.id);
+    Moves {
+        top: first,
+        up: index
+            .checked_sub(1)
+            .map(|prev| tasks[prev])
+            .filter(|task| same_key(&task))
+            .map(|task| task.id),
+        down: tasks.get(index + 1).filter(same_key).map(|task| task.id),
+    }
+}
+
+/// The Monday after `today`, when snoozing a task until next week.
+fn next_week(today: NaiveDate) -> NaiveDate {
+    today + Duration::days(7 - i64::from(today.weekday().num_days_from_monday()))
+}
+
+/// Arrange the sorted `tasks` into rows, with subtasks nested under their parent.
+/// Subtasks whose parent isn't in `tasks`, such as when filtering by tag, are shown
+/// on their own.
+fn rows<'a>(tasks: &[&'a models::Task]) -> Vec<Row<'a>> {
+    let top = tasks
+        .iter()
+        .copied()
+        .filter(|task| {
+            task.parent
+                .map_or(true, |parent| !tasks.iter().any(|other| other.id == parent))
+        })
+        .collect();
+    nest(tasks, top)
+}
+
+fn nest<'a>(tasks: &[&'a models::Task], siblings: Vec<&'a models::Task>) -> Vec<Row<'a>> {
+    siblings
+        .iter()
+        .enumerate()
+        .map(|(index, &task)| {
+            let subtasks = tasks
+                .iter()
+                .copied()
+                .filter(|other| other.parent == Some(task.id))
+                .collect();
+            Row {
+                task,
+                moves: moves(&siblings, index),
+                subtasks: nest(tasks, subtasks),
+            }
+        })
+        .collect()
+}
+
+fn task_class(task: &models::Task, today: NaiveDate) -> Option<String> {
+    let priority_class = match task.priority {
+        Priority::None => None,
+        priority => Some(format!("priority-{}", priority)),
+    };
+    let classes = priority_class
+        .into_iter()
+        .chain(due_class(task, today).map(String::from))
+        .collect::<Vec<_>>();
+    if classes.is_empty() {
+        None
+    } else {
+        Some(classes.join(" "))
+    }
+}
+
+fn priority_name(priority: Priority) -> &'static str {
+    match priority {
+        Priority::None => "None",
+        Priority::Low => "Low",
+        Priority::Normal => "Normal",
+        Priority::High => "High",
+    }
+}
+
+/// The label shown next to tasks with a priority that stands out.
+fn priority_label(priority: Priority) -> Option<&'static str> {
+    match priority {
+        Priority::High => Some("High priority"),
+        Priority::Low => Some("Low priority"),
+        Priority::Normal | Priority::None => None,
+    }
+}
+
+fn due_class(task: &models::Task, today: NaiveDate) -> Option<&'static str> {
+    if task.is_overdue(today) {
+        Some("overdue")
+    } else if task.is_due_today(today) {
+        Some("due-today")
+    } else {
+        None
+    }
+}
+
+fn due_label(due: NaiveDate, today: NaiveDate) -> String {
+    if due == today {
+        String::from("Due today")
+    } else if due == today + Duration::days(1) {
+        String::from("Due tomorrow")
+    } else if due.year() == today.year() {
+        format!("Due {}", due.format("%-d %b"))
+    } else {
+        format!("Due {}", due.format("%-d %b %Y"))
+    }
+}
+
+fn age_class(time: Timestamp, now: Timestamp) -> &'static str {
+    if now.signed_duration_since(time).num_days() >= STALE_AGE_DAYS {
+        "age stale"
+    } else {
+        "age"
+    }
+}
+
+/// Group completed tasks into runs of tasks completed on the same day.
+///
+/// Tasks are expected to already be sorted by completion time.
+pub fn group_by_day<'a>(
+    tasks: &[&'a models::CompletedTask],
+) -> Vec<(NaiveDate, Vec<&'a models::CompletedTask>)> {
+    let mut days: Vec<(NaiveDate, Vec<&models::CompletedTask>)> = Vec::new();
+    for &task in tasks {
+        let day = task.completed_at.naive_utc().date();
+        match days.last_mut() {
+            Some((last_day, day_tasks)) if *last_day == day => day_tasks.push(task),
+            _ => days.push((day, vec![task])),
+        }
+    }
+    days
+}
 
+impl<'a> Render for AutoLink<'a> {
+    fn render(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut start = 0;
-        for url_match in RE.find_iter(self.0) {
+        for url_match in URL_RE.find_iter(self.0) {
             // Write out the text preceding the URL escaped
-            &self.0[start..url_match.start()].render(f)?;
-            // Write out the URL as a link, unescaped
-            markup::raw(format!(
-                r#"<a href="{url}" target="_blank">{url}</a>"#,
-                url = url_match.as_str()
-            ))
+            self.0[start..url_match.start()].render(f)?;
+            // Write out the URL as a link, also escaped
+            Link {
+                url: url_match.as_str(),
+            }
             .render(f)?;
             // Update the start marker
             start = url_match.end()
         }
 
         if start < self.0.len() {
-            &self.0[start..].render(f)?;
+            self.0[start..].render(f)?;
         }
 
         Ok(())
@@ -125,6 +712,161 @@ mod tests {
         }
     }
 
+    fn task(priority: Priority, due: Option<NaiveDate>) -> models::Task {
+        models::Task {
+            id: rusty_ulid::Ulid::generate(),
+            description: String::from("task"),
+            created_at: Utc::now(),
+            due,
+            priority,
+            notes: None,
+            recurrence: None,
+            hidden_until: None,
+            parent: None,
+        }
+    }
+
+    #[test]
+    fn test_relative_age() {
+        use chrono::TimeZone;
+
+        let now = Utc.ymd(2026, 10, 18).and_hms(12, 0, 0);
+        let ago = |duration| relative_age(now - duration, now);
+        assert_eq!(ago(Duration::seconds(30)), "just now");
+        assert_eq!(ago(Duration::seconds(-30)), "just now");
+        assert_eq!(ago(Duration::minutes(1)), "1 minute ago");
+        assert_eq!(ago(Duration::minutes(59)), "59 minutes ago");
+        assert_eq!(ago(Duration::hours(5)), "5 hours ago");
+        assert_eq!(ago(Duration::days(1)), "1 day ago");
+        assert_eq!(ago(Duration::days(3)), "3 days ago");
+        assert_eq!(ago(Duration::days(20)), "2 weeks ago");
+        assert_eq!(ago(Duration::days(90)), "3 months ago");
+        assert_eq!(ago(Duration::days(800)), "2 years ago");
+        assert_eq!(age_class(now - Duration::days(27), now), "age");
+        assert_eq!(age_class(now - Duration::days(28), now), "age stale");
+    }
+
+    #[test]
+    fn test_due() {
+        let today = NaiveDate::from_ymd(2026, 10, 18);
+        assert_eq!(due_label(today, today), "Due today");
+        assert_eq!(due_label(today.succ(), today), "Due tomorrow");
+        assert_eq!(due_label(today.pred(), today), "Due 17 Oct");
+        assert_eq!(
+            due_label(NaiveDate::from_ymd(2027, 1, 5), today),
+            "Due 5 Jan 2027"
+        );
+
+        let task = |due| task(Priority::None, due);
+        let (none, overdue, later) = (
+            task(None),
+            task(Some(today.pred())),
+            task(Some(today.succ())),
+        );
+        let due_today = task(Some(today));
+        assert_eq!(due_class(&overdue, today), Some("overdue"));
+        assert_eq!(due_class(&due_today, today), Some("due-today"));
+        assert_eq!(due_class(&later, today), None);
+        assert_eq!(due_class(&none, today), None);
+
+        let mut tasks = vec![&none, &later, &due_today, &overdue];
+        sort_tasks(&mut tasks);
+        let ids = tasks.iter().map(|task| task.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![overdue.id, due_today.id, later.id, none.id]);
+    }
+
+    #[test]
+    fn test_priority() {
+        let today = NaiveDate::from_ymd(2026, 10, 18);
+        let low = task(Priority::Low, Some(today));
+        let none = task(Priority::None, None);
+        let normal = task(Priority::Normal, Some(today.succ()));
+        let high = task(Priority::High, None);
+        let overdue_high = task(Priority::High, Some(today.pred()));
+
+        let mut tasks = vec![&low, &none, &normal, &high, &overdue_high];
+        sort_tasks(&mut tasks);
+        let ids = tasks.iter().map(|task| task.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![overdue_high.id, high.id, normal.id, none.id, low.id]
+        );
+
+        assert_eq!(
+            task_class(&overdue_high, today).as_deref(),
+            Some("priority-high overdue")
+        );
+        assert_eq!(
+            task_class(&normal, today).as_deref(),
+            Some("priority-normal")
+        );
+        assert_eq!(task_class(&none, today), None);
+    }
+
+    #[test]
+    fn test_next_week() {
+        let monday = NaiveDate::from_ymd(2026, 10, 19);
+        assert_eq!(next_week(NaiveDate::from_ymd(2026, 10, 18)), monday);
+        assert_eq!(next_week(NaiveDate::from_ymd(2026, 10, 14)), monday);
+        assert_eq!(next_week(monday), NaiveDate::from_ymd(2026, 10, 26));
+    }
+
+    #[test]
+    fn test_moves() {
+        let task = |due| task(Priority::None, due);
+        let today = NaiveDate::from_ymd(2026, 10, 18);
+        let (dated, first, second, third) = (task(Some(today)), task(None), task(None), task(None));
+        let tasks = vec![&dated, &first, &second, &third];
+
+        // Tasks don't move past tasks that sort differently
+        let none = Moves {
+            top: None,
+            up: None,
+            down: None,
+        };
+        assert_eq!(moves(&tasks, 0), none);
+        assert_eq!(
+            moves(&tasks, 1),
+            Moves {
+                down: Some(second.id),
+                ..none
+            }
+        );
+        assert_eq!(
+            moves(&tasks, 3),
+            Moves {
+                top: Some(first.id),
+                up: Some(second.id),
+                down: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_rows() {
+        let task = |parent| models::Task {
+            parent,
+            ..task(Priority::None, None)
+        };
+        let parent = task(None);
+        let (sub1, sub2) = (task(Some(parent.id)), task(Some(parent.id)));
+        let other = task(None);
+        let tasks = vec![&parent, &sub1, &other, &sub2];
+
+        let rows = rows(&tasks);
+        let ids = |rows: &[Row]| rows.iter().map(|row| row.task.id).collect::<Vec<_>>();
+        assert_eq!(ids(&rows), vec![parent.id, other.id]);
+        assert_eq!(ids(&rows[0].subtasks), vec![sub1.id, sub2.id]);
+        assert!(rows[1].subtasks.is_empty());
+        // Moves are among siblings
+        assert_eq!(rows[0].moves.down, Some(other.id));
+        assert_eq!(rows[0].subtasks[1].moves.up, Some(sub1.id));
+
+        // Subtasks are shown on their own when their parent isn't
+        let rows = super::rows(&[&sub1, &other]);
+        assert_eq!(ids(&rows), vec![sub1.id, other.id]);
+    }
+
     #[test]
     fn test_autolink() {
         assert_eq!(AutoLink("").to_string(), String::from(""));
@@ -154,5 +896,20 @@ mod tests {
                 r#"<a href="http://example.com/" target="_blank">http://example.com/</a> <a href="https://example.com/" target="_blank">https://example.com/</a>"#
             )
         );
+        assert_eq!(
+            AutoLink(r#"<https://example.com/?a=1&b=2>, "https://example.com/'x"#).to_string(),
+            String::from(
+                r#"&lt;<a href="https://example.com/?a=1&amp;b=2" target="_blank">https://example.com/?a=1&amp;b=2</a>&gt;, &quot;<a href="https://example.com/" target="_blank">https://example.com/</a>'x"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_urls() {
+        assert!(urls("no url").is_empty());
+        assert_eq!(
+            urls("Read https://example.com/a and http://example.org/ then https://example.com/a"),
+            vec!["https://example.com/a", "http://example.org/"]
+        );
     }
 }
@@ -0,0 +1,291 @@
+//! API tokens.
+//!
+//! Each token has a name, a set of scopes that limit what it can be used for, and a
+//! secret. Only a SHA-256 hash of the secret is stored, so the secret is shown once
+//! when the token is created.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use chrono::{SubsecRound, Utc};
+use rand::Rng;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use crate::models::Timestamp;
+use crate::store::{self, read_records, replace_records};
+
+/// How often the time tokens were last used is written out. Uses in between are
+/// only recorded in memory, so that API requests don't each rewrite the file.
+const LAST_USED_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    TasksAdd,
+    TasksComplete,
+    TasksEdit,
+    TasksRead,
+}
+
+/// A set of scopes, stored as a space separated list.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Scopes(Vec<Scope>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub name: String,
+    hash: String,
+    pub scopes: Scopes,
+    pub created_at: Timestamp,
+    pub last_used_at: Option<Timestamp>,
+}
+
+pub struct TokenRegistry {
+    path: PathBuf,
+    tokens: Vec<ApiToken>,
+    /// When the tokens were last written out after being used.
+    last_used_saved_at: Option<Instant>,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::TasksAdd,
+        Scope::TasksComplete,
+        Scope::TasksEdit,
+        Scope::TasksRead,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::TasksAdd => "tasks:add",
+            Scope::TasksComplete => "tasks:complete",
+            Scope::TasksEdit => "tasks:edit",
+            Scope::TasksRead => "tasks:read",
+        }
+    }
+}
+
+impl Scopes {
+    pub fn all() -> Self {
+        Scopes(Scope::ALL.to_vec())
+    }
+
+    pub fn contains(&self, scope: Scope) -> bool {
+        self.0.contains(&scope)
+    }
+
+    pub fn insert(&mut self, scope: Scope) {
+        if !self.contains(scope) {
+            self.0.push(scope);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Scope> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl TokenRegistry {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, store::Error> {
+        let path = path.as_ref().to_owned();
+        let tokens = read_records(&path)?;
+        Ok(TokenRegistry {
+            path,
+            tokens,
+            last_used_saved_at: None,
+        })
+    }
+
+    pub fn list(&self) -> &[ApiToken] {
+        &self.tokens
+    }
+
+    /// Create a new token, returning its secret. Any existing token with the same
+    /// name is replaced.
+    pub fn create(&mut self, name: &str, scopes: Scopes) -> Result<String, store::Error> {
+        let secret = generate_secret();
+        let mut tokens = self
+            .tokens
+            .iter()
+            .filter(|token| token.name != name)
+            .cloned()
+            .collect::<Vec<_>>();
+        tokens.push(ApiToken {
+            name: name.to_string(),
+            hash: hash_secret(&secret),
+            scopes,
+            created_at: Utc::now().trunc_subsecs(0),
+            last_used_at: None,
+        });
+
+        replace_records(&self.path, &tokens)?;
+        self.tokens = tokens;
+        Ok(secret)
+    }
+
+    /// Find the token with the supplied secret and record that it was used. The
+    /// time is written out at most once every `LAST_USED_SAVE_INTERVAL`.
+    pub fn authenticate(&mut self, secret: &str) -> Option<&ApiToken> {
+        let hash = hash_secret(secret);
+        let index = self.tokens.iter().position(|token| token.hash == hash)?;
+        self.tokens[index].last_used_at = Some(Utc::now().trunc_subsecs(0));
+        let save = self.last_used_saved_at.map_or(true, |saved_at| {
+            saved_at.elapsed() >= LAST_USED_SAVE_INTERVAL
+        });
+        if save {
+            self.last_used_saved_at = Some(Instant::now());
+            if let Err(err) = replace_records(&self.path, &self.tokens) {
+                // Not fatal, the token is still valid
+                log::error!("unable to record token use: {}", err);
+            }
+        }
+        Some(&self.tokens[index])
+    }
+
+    /// Revoke the token named `name`. Returns `false` if there is no such token.
+    pub fn revoke(&mut self, name: &str) -> Result<bool, store::Error> {
+        let tokens = self
+            .tokens
+            .iter()
+            .filter(|token| token.name != name)
+            .cloned()
+            .collect::<Vec<_>>();
+        if tokens.len() == self.tokens.len() {
+            return Ok(false);
+        }
+
+        replace_records(&self.path, &tokens)?;
+        self.tokens = tokens;
+        Ok(true)
+    }
+}
+
+fn generate_secret() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    to_hex(&bytes)
+}
+
+fn hash_secret(secret: &str) -> String {
+    to_hex(&Sha256::digest(secret.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .iter()
+            .copied()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("unknown scope '{}'", s))
+    }
+}
+
+impl fmt::Display for Scopes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scopes = self.0.iter().map(Scope::as_str).collect::<Vec<_>>();
+        f.write_str(&scopes.join(" "))
+    }
+}
+
+impl FromStr for Scopes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut scopes = Scopes::default();
+        for scope in s.split_whitespace() {
+            scopes.insert(scope.parse()?);
+        }
+        Ok(scopes)
+    }
+}
+
+impl Serialize for Scopes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Scopes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKENS_FILENAME: &str = "tokens.csv";
+
+    #[test]
+    fn test_scopes() {
+        let scopes = "tasks:read tasks:add tasks:read".parse::<Scopes>().unwrap();
+        assert!(scopes.contains(Scope::TasksRead));
+        assert!(scopes.contains(Scope::TasksAdd));
+        assert!(!scopes.contains(Scope::TasksComplete));
+        assert_eq!(scopes.to_string(), "tasks:read tasks:add");
+        assert!("tasks:delete".parse::<Scopes>().is_err());
+    }
+
+    #[test]
+    fn test_registry() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        let path = testdir.path().join(TOKENS_FILENAME);
+
+        let mut registry = TokenRegistry::open(&path).unwrap();
+        let phone = registry
+            .create("phone", "tasks:add".parse().unwrap())
+            .unwrap();
+        let script = registry.create("script", Scopes::all()).unwrap();
+        assert_ne!(phone, script);
+        assert_eq!(phone.len(), 64);
+        assert!(registry.authenticate("wrong").is_none());
+
+        let token = registry.authenticate(&phone).unwrap();
+        assert_eq!(token.name, "phone");
+        assert!(token.scopes.contains(Scope::TasksAdd));
+        assert!(token.last_used_at.is_some());
+
+        // Uses soon after the last one saved are only recorded in memory
+        assert!(registry
+            .authenticate(&script)
+            .unwrap()
+            .last_used_at
+            .is_some());
+        let saved = TokenRegistry::open(&path).unwrap();
+        assert!(saved.list()[0].last_used_at.is_some());
+        assert!(saved.list()[1].last_used_at.is_none());
+
+        // Secrets are not stored
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains(&phone));
+
+        assert!(registry.revoke("phone").unwrap());
+        assert!(!registry.revoke("phone").unwrap());
+
+        // Changes are persisted
+        let mut registry = TokenRegistry::open(&path).unwrap();
+        assert!(registry.authenticate(&phone).is_none());
+        assert_eq!(registry.authenticate(&script).unwrap().name, "script");
+        assert_eq!(registry.list().len(), 1);
+    }
+}
@@ -0,0 +1,260 @@
+//! User accounts.
+//!
+//! Leaf either has a single account, configured with a password hash, or multiple
+//! accounts read from a users directory. The users directory contains a `users` file
+//! with one `username:password_hash` line per account, and a subdirectory per user
+//! holding that user's lists and API tokens.
+
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{fmt, fs};
+
+use crate::lists::{self, Lists};
+use crate::store;
+use crate::tokens::TokenRegistry;
+
+pub const DEFAULT_USER: &str = "default";
+pub const USERS_FILENAME: &str = "users";
+pub const TOKENS_FILENAME: &str = "tokens.csv";
+
+pub struct Account {
+    pub username: String,
+    pub password_hash: String,
+    pub lists: Lists,
+    pub tokens: Mutex<TokenRegistry>,
+}
+
+pub struct Users {
+    accounts: Vec<Account>,
+    multi_user: bool,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    InvalidLine(PathBuf, usize),
+    Empty(PathBuf),
+    UnknownUser(String),
+    List(lists::Error),
+    Tokens(PathBuf, store::Error),
+}
+
+impl Users {
+    /// A single account with the supplied password hash, lists, and tokens.
+    pub fn single(password_hash: String, lists: Lists, tokens: TokenRegistry) -> Self {
+        Users {
+            accounts: vec![Account {
+                username: DEFAULT_USER.to_string(),
+                password_hash,
+                lists,
+                tokens: Mutex::new(tokens),
+            }],
+            multi_user: false,
+        }
+    }
+
+    /// Read the accounts from the users directory at `dir`.
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        let mut accounts = Vec::new();
+        for (username, password_hash) in read_users(dir)? {
+            let user_dir = dir.join(&username);
+            let lists = open_lists(&user_dir)?;
+            let tokens_path = user_dir.join(TOKENS_FILENAME);
+            let tokens =
+                TokenRegistry::open(&tokens_path).map_err(|err| Error::Tokens(tokens_path, err))?;
+            accounts.push(Account {
+                username,
+                password_hash,
+                lists,
+                tokens: Mutex::new(tokens),
+            });
+        }
+
+        Ok(Users {
+            accounts,
+            multi_user: true,
+        })
+    }
+
+    /// Open only the lists of the account named `username` in the users directory at
+    /// `dir`, or of the first account when `None`.
+    pub fn open_lists(dir: &Path, username: Option<&str>) -> Result<Lists, Error> {
+        let users = read_users(dir)?;
+        let username = match username {
+            Some(username) => users
+                .iter()
+                .map(|(name, _)| name)
+                .find(|name| *name == username)
+                .ok_or_else(|| Error::UnknownUser(username.to_string()))?,
+            // read_users returns at least one user
+            None => &users[0].0,
+        };
+        open_lists(&dir.join(username))
+    }
+
+    pub fn is_multi_user(&self) -> bool {
+        self.multi_user
+    }
+
+    pub fn get(&self, username: &str) -> Option<&Account> {
+        self.accounts
+            .iter()
+            .find(|account| account.username == username)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.iter()
+    }
+
+    /// The first account. `LEAF_API_TOKEN` acts on behalf of this account.
+    pub fn owner(&self) -> &Account {
+        &self.accounts[0]
+    }
+}
+
+/// Read the `username:password_hash` lines of the users file in `dir`. Returns an
+/// error if there are none.
+fn read_users(dir: &Path) -> Result<Vec<(String, String)>, Error> {
+    let path = dir.join(USERS_FILENAME);
+    let file = fs::File::open(&path).map_err(|err| Error::Io(path.clone(), err))?;
+
+    let mut users: Vec<(String, String)> = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|err| Error::Io(path.clone(), err))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (username, password_hash) = parse_line(line)
+            .filter(|(username, _)| !users.iter().any(|(name, _)| name == username))
+            .ok_or_else(|| Error::InvalidLine(path.clone(), i + 1))?;
+        users.push((username.to_string(), password_hash.to_string()));
+    }
+
+    if users.is_empty() {
+        return Err(Error::Empty(path));
+    }
+    Ok(users)
+}
+
+/// The lists in a user's directory.
+fn open_lists(user_dir: &Path) -> Result<Lists, Error> {
+    let default = lists::open(user_dir).map_err(Error::List)?;
+    Lists::new(default, Some(user_dir.join("lists"))).map_err(Error::List)
+}
+
+/// Parse a `username:password_hash` line. Usernames follow the same rules as list
+/// names as they are used as directory names.
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let mut parts = line.splitn(2, ':');
+    let username = parts.next()?.trim();
+    let password_hash = parts.next()?.trim();
+    if lists::is_valid_name(username) && !password_hash.is_empty() {
+        Some((username, password_hash))
+    } else {
+        None
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, _) => write!(f, "Unable to read users ({})", path.display()),
+            Error::InvalidLine(path, line) => write!(
+                f,
+                "Invalid or duplicate user on line {} of {}",
+                line,
+                path.display()
+            ),
+            Error::Empty(path) => write!(f, "No users found in {}", path.display()),
+            Error::UnknownUser(username) => write!(f, "No user named {}", username),
+            Error::List(err) => err.fmt(f),
+            Error::Tokens(path, _) => write!(f, "Unable to read tokens ({})", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, err) => Some(err),
+            Error::InvalidLine(_, _) | Error::Empty(_) | Error::UnknownUser(_) => None,
+            Error::List(err) => Some(&err.source),
+            Error::Tokens(_, err) => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str =
+        "$argon2i$v=19$m=4096,t=3,p=1$c2FsdHNhbHQ$DKlexoEJUoZTmkAAC3SaMWk30El9/RvVhlqGo6afIng";
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line(&format!("alice:{}", HASH)),
+            Some(("alice", HASH))
+        );
+        assert_eq!(parse_line("alice:"), None);
+        assert_eq!(parse_line(&format!("../alice:{}", HASH)), None);
+        assert_eq!(parse_line("alice"), None);
+    }
+
+    #[test]
+    fn test_open() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        fs::write(
+            testdir.path().join(USERS_FILENAME),
+            format!("# Comment\nalice:{0}\n\nbob:{0}\n", HASH),
+        )
+        .unwrap();
+
+        let users = Users::open(testdir.path()).unwrap();
+        assert!(users.is_multi_user());
+        assert_eq!(users.owner().username, "alice");
+        assert!(users.get("carol").is_none());
+        let bob = users.get("bob").unwrap();
+        assert_eq!(bob.password_hash, HASH);
+        assert!(bob.lists.is_enabled());
+        assert!(testdir.path().join("bob").is_dir());
+    }
+
+    #[test]
+    fn test_open_lists() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        fs::write(
+            testdir.path().join(USERS_FILENAME),
+            format!("alice:{0}\nbob:{0}\n", HASH),
+        )
+        .unwrap();
+
+        assert!(Users::open_lists(testdir.path(), None).is_ok());
+        assert!(testdir.path().join("alice").is_dir());
+        assert!(!testdir.path().join("bob").exists());
+        assert!(Users::open_lists(testdir.path(), Some("bob")).is_ok());
+        assert!(testdir.path().join("bob").is_dir());
+        match Users::open_lists(testdir.path(), Some("carol")) {
+            Err(Error::UnknownUser(username)) => assert_eq!(username, "carol"),
+            _ => panic!("expected unknown user error"),
+        }
+    }
+
+    #[test]
+    fn test_open_duplicate() {
+        let testdir = tempfile::tempdir().expect("unable to create tempdir");
+        fs::write(
+            testdir.path().join(USERS_FILENAME),
+            format!("alice:{0}\nalice:{0}\n", HASH),
+        )
+        .unwrap();
+
+        match Users::open(testdir.path()) {
+            Err(Error::InvalidLine(_, 2)) => {}
+            _ => panic!("expected invalid line error"),
+        }
+    }
+}